@@ -0,0 +1,203 @@
+use rayon::ThreadPool;
+use std::time::{Duration, Instant};
+
+use lib::carrier::Carriers;
+use lib::manip::{ManipulatorMode, VertexManipulator};
+use lib::mesh::IndexedMesh;
+
+/*
+ *  Times the vertex channel on a synthetic grid mesh, bit by bit against the chunked path on one
+ *  thread and on every thread rayon finds, then a whole encode and decode through the carriers.
+ *
+ *      cargo bench --bench vertex_channel
+ *      STEGA_BENCH_GRID=3000 cargo bench --bench vertex_channel    (9M vertices, 18M triangles)
+ */
+const BITS: u8 = 8;
+const SEED: [u8; 32] = [42u8; 32];
+
+fn grid(side: u32) -> Vec<stl::Triangle> {
+    let point = |x: u32, y: u32| {
+        let (fx, fy) = (x as f32 * 0.37, y as f32 * 0.41);
+        [fx, fy, (fx * 0.05).sin() * (fy * 0.07).cos() * 12.0 + 20.0]
+    };
+    let mut triangles = Vec::<stl::Triangle>::with_capacity((side as usize - 1).pow(2) * 2);
+    for y in 0..side - 1 {
+        for x in 0..side - 1 {
+            for (v1, v2, v3) in [
+                (point(x, y), point(x + 1, y), point(x + 1, y + 1)),
+                (point(x, y), point(x + 1, y + 1), point(x, y + 1)),
+            ] {
+                triangles.push(stl::Triangle {
+                    normal: [0.0, 0.0, 1.0],
+                    v1,
+                    v2,
+                    v3,
+                    attr_byte_count: 0,
+                });
+            }
+        }
+    }
+    triangles
+}
+
+fn payload(bit_count: usize) -> Vec<u8> {
+    (0..bit_count)
+        .map(|i| ((i * 7919) % 13 < 6) as u8)
+        .collect()
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn on_pool<T: Send>(pool: Option<&ThreadPool>, f: impl FnOnce() -> T + Send) -> T {
+    match pool {
+        Some(pool) => pool.install(f),
+        None => f(),
+    }
+}
+
+fn report(name: &str, baseline: Duration, time: Duration) {
+    println!(
+        "{:<40}{:>10.1} ms{:>9.2}x",
+        name,
+        time.as_secs_f64() * 1000.0,
+        baseline.as_secs_f64() / time.as_secs_f64()
+    );
+}
+
+fn manipulator(vertices: &[[u32; 3]], bits: &[u8], scatter_bits: bool, one_thread: &ThreadPool) {
+    // the manipulators are made before the clock starts, only the walk itself is timed
+    let make = |mode, vertices: &[[u32; 3]]| {
+        let vman = VertexManipulator::new(mode, vertices.to_vec(), BITS)
+            .unwrap()
+            .with_scattered_vertices(SEED);
+        if scatter_bits {
+            vman.with_scattered_bits(SEED)
+        } else {
+            vman
+        }
+    };
+    let label = if scatter_bits { " (scatter-bits)" } else { "" };
+    let pools = [("1 thread", Some(one_thread)), ("all threads", None)];
+
+    let mut vman = make(ManipulatorMode::WRITE, vertices);
+    let (_, write_baseline) = timed(|| {
+        for bit in bits.iter() {
+            vman.set_next_bit_in_vertex(*bit);
+        }
+    });
+    let encoded = vman.into_vertices();
+    report(
+        &format!("write bit by bit{}", label),
+        write_baseline,
+        write_baseline,
+    );
+    for (threads, pool) in pools {
+        let mut vman = make(ManipulatorMode::WRITE, vertices);
+        let (_, time) = timed(|| on_pool(pool, || vman.write_bits(bits)));
+        assert!(vman.into_vertices() == encoded);
+        report(
+            &format!("write_bits, {}{}", threads, label),
+            write_baseline,
+            time,
+        );
+    }
+
+    let mut vman = make(ManipulatorMode::READ, &encoded);
+    let (read, read_baseline) = timed(|| {
+        bits.iter()
+            .map(|_| vman.next_bit_from_vertex())
+            .collect::<Vec<u8>>()
+    });
+    assert!(read == bits);
+    report(
+        &format!("read bit by bit{}", label),
+        read_baseline,
+        read_baseline,
+    );
+    for (threads, pool) in pools {
+        let mut vman = make(ManipulatorMode::READ, &encoded);
+        let (read, time) = timed(|| on_pool(pool, || vman.read_bits(bits.len())));
+        assert!(read == bits);
+        report(
+            &format!("read_bits, {}{}", threads, label),
+            read_baseline,
+            time,
+        );
+    }
+}
+
+fn carriers(triangles: &[stl::Triangle], mesh: &IndexedMesh, bits: &[u8]) -> Vec<u8> {
+    let carriers = Carriers {
+        vertex_bits: Some(BITS),
+        ..Default::default()
+    };
+    let stl = stl::BinaryStlFile {
+        header: stl::BinaryStlHeader {
+            header: [0u8; 80],
+            num_triangles: triangles.len() as u32,
+        },
+        triangles: triangles.iter().map(lib::manip::copy_triangle).collect(),
+    };
+    let mut writer = carriers
+        .writer(mesh, |v, bits| {
+            VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+        })
+        .unwrap();
+    for bit in bits.iter() {
+        writer.write_bit(*bit);
+    }
+    let encoded = writer.finish(&stl.header, mesh).stl;
+
+    let encoded_mesh = IndexedMesh::from_triangles(&encoded.triangles);
+    let mut reader = carriers
+        .reader(&encoded_mesh, |v, bits| {
+            VertexManipulator::new(ManipulatorMode::READ, v, bits)
+        })
+        .unwrap();
+    bits.iter().map(|_| reader.next_bit()).collect()
+}
+
+fn main() {
+    let side: u32 = std::env::var("STEGA_BENCH_GRID")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1500);
+    let triangles = grid(side);
+    let (mesh, index_time) = timed(|| IndexedMesh::from_triangles(&triangles));
+    let vertices = mesh.vertex_bits();
+    let bits = payload(vertices.len() * 3 * BITS as usize);
+    println!(
+        "{} triangles, {} vertices, {} payload bits at depth {}, {} threads",
+        triangles.len(),
+        vertices.len(),
+        bits.len(),
+        BITS,
+        rayon::current_num_threads()
+    );
+    println!(
+        "indexed mesh built in {:.1} ms",
+        index_time.as_secs_f64() * 1000.0
+    );
+    println!("{:<40}{:>13}{:>10}", "", "time", "speedup");
+
+    let one_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    manipulator(&vertices, &bits, false, &one_thread);
+    manipulator(&vertices, &bits, true, &one_thread);
+
+    let (_, carrier_one) = timed(|| one_thread.install(|| carriers(&triangles, &mesh, &bits)));
+    let (read, carrier_all) = timed(|| carriers(&triangles, &mesh, &bits));
+    assert!(read == bits);
+    report("carrier encode+decode, 1 thread", carrier_one, carrier_one);
+    report(
+        "carrier encode+decode, all threads",
+        carrier_one,
+        carrier_all,
+    );
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+/*
+ *  The 16 bit "attribute byte count" after every triangle is meant to be 0, and most tools
+ *  write 0 there, but VisCAM/SolidView and Materialise use it for a 15 bit facet color:
+ *  5 bits each of red (bits 10-14), green (5-9) and blue (0-4), with bit 15 as a flag.
+ *
+ *  Raw mode uses all 16 bits, which stands out to anything that reads colors.
+ *  Color mode only uses the low bits of each channel, on top of the color already there or a
+ *  neutral grey with the VisCAM "color valid" flag set, so the model just looks faintly
+ *  speckled in a viewer that shows facet colors.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttributeMode {
+    Raw,
+    Color(u8),
+}
+
+const COLOR_VALID: u16 = 0x8000;
+const NEUTRAL_GREY: u16 = COLOR_VALID | 16 << 10 | 16 << 5 | 16;
+
+fn attribute_shifts(mode: AttributeMode) -> Vec<u8> {
+    match mode {
+        AttributeMode::Raw => (0..16).rev().collect(),
+        AttributeMode::Color(bits) => {
+            assert!(bits > 0 && bits <= 5);
+            [10, 5, 0]
+                .iter()
+                .flat_map(|channel| (0..bits).rev().map(move |b| channel + b))
+                .collect()
+        }
+    }
+}
+
+pub fn capacity_bits(triangles: &[stl::Triangle], mode: AttributeMode) -> u64 {
+    triangles.len() as u64 * attribute_shifts(mode).len() as u64
+}
+
+pub struct AttributeUsage {
+    pub in_use: usize,
+    pub distinct: usize,
+    pub color_flagged: usize,
+}
+
+pub fn usage(triangles: &[stl::Triangle]) -> AttributeUsage {
+    // how much of the model already has something other than 0 in its attribute bytes
+    let in_use: Vec<u16> = triangles
+        .iter()
+        .map(|t| t.attr_byte_count)
+        .filter(|a| *a != 0)
+        .collect();
+    AttributeUsage {
+        in_use: in_use.len(),
+        distinct: in_use.iter().collect::<HashSet<_>>().len(),
+        color_flagged: in_use.iter().filter(|a| **a & COLOR_VALID != 0).count(),
+    }
+}
+
+pub struct AttributeWriter {
+    values: Vec<u16>,
+    shifts: Vec<u8>,
+    position: u64,
+}
+
+impl AttributeWriter {
+    pub fn new(triangles: &[stl::Triangle], mode: AttributeMode) -> AttributeWriter {
+        let values = triangles
+            .iter()
+            .map(|t| match (mode, t.attr_byte_count) {
+                (AttributeMode::Color(_), 0) => NEUTRAL_GREY,
+                (_, existing) => existing,
+            })
+            .collect();
+        AttributeWriter {
+            values,
+            shifts: attribute_shifts(mode),
+            position: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.values.len() as u64 * self.shifts.len() as u64
+    }
+
+    pub fn pushed_bits(&self) -> u64 {
+        self.position
+    }
+
+    pub fn push_bit(&mut self, bit: u8) {
+        if self.position >= self.capacity_bits() {
+            return;
+        }
+        let per_triangle = self.shifts.len() as u64;
+        let triangle = (self.position / per_triangle) as usize;
+        let shift = self.shifts[(self.position % per_triangle) as usize];
+
+        let value = &mut self.values[triangle];
+        *value = *value & !(1 << shift) | (bit as u16) << shift;
+        self.position += 1;
+    }
+
+    pub fn apply(&self, triangles: &mut [stl::Triangle]) {
+        // triangles must be in the order the writer was created with
+        for (tri, value) in triangles.iter_mut().zip(self.values.iter()) {
+            tri.attr_byte_count = *value;
+        }
+    }
+}
+
+pub struct AttributeReader {
+    values: Vec<u16>,
+    shifts: Vec<u8>,
+    position: u64,
+}
+
+impl AttributeReader {
+    pub fn new(triangles: &[stl::Triangle], mode: AttributeMode) -> AttributeReader {
+        AttributeReader {
+            values: triangles.iter().map(|t| t.attr_byte_count).collect(),
+            shifts: attribute_shifts(mode),
+            position: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.values.len() as u64 * self.shifts.len() as u64
+    }
+
+    pub fn read_bits(&self) -> u64 {
+        self.position
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        if self.position >= self.capacity_bits() {
+            return 0;
+        }
+        let per_triangle = self.shifts.len() as u64;
+        let triangle = (self.position / per_triangle) as usize;
+        let shift = self.shifts[(self.position % per_triangle) as usize];
+        self.position += 1;
+
+        (self.values[triangle] >> shift & 1) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangles(attr_byte_count: u16) -> Vec<stl::Triangle> {
+        (0..8)
+            .map(|i| stl::Triangle {
+                normal: [0.0, 0.0, 1.0],
+                v1: [i as f32, 0.0, 0.0],
+                v2: [0.0, i as f32, 0.0],
+                v3: [0.0, 0.0, i as f32],
+                attr_byte_count,
+            })
+            .collect()
+    }
+
+    fn roundtrip(triangles: &mut [stl::Triangle], mode: AttributeMode, payload: &[u8]) {
+        let mut writer = AttributeWriter::new(triangles, mode);
+        for bit in payload.iter() {
+            writer.push_bit(*bit);
+        }
+        writer.apply(triangles);
+        let mut reader = AttributeReader::new(triangles, mode);
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+
+    #[test]
+    fn test_attribute_roundtrip() {
+        let payload: Vec<u8> = (0..40).map(|i| ((i * 7) % 4 < 2) as u8).collect();
+
+        let mut raw = triangles(0);
+        assert_eq!(capacity_bits(&raw, AttributeMode::Raw), 128);
+        roundtrip(&mut raw, AttributeMode::Raw, &payload);
+
+        // an existing red stays red, only its low bits move
+        let red: u16 = COLOR_VALID | 31 << 10;
+        let mut colored = triangles(red);
+        assert_eq!(usage(&colored).color_flagged, 8);
+        roundtrip(&mut colored, AttributeMode::Color(2), &payload);
+        for tri in colored.iter() {
+            assert_eq!(
+                tri.attr_byte_count & !0b00011_00011_00011,
+                red & !0b00011_00011_00011
+            );
+        }
+        assert_eq!(usage(&triangles(0)).in_use, 0);
+    }
+}
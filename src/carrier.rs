@@ -0,0 +1,716 @@
+use crate::attributes::{AttributeMode, AttributeReader, AttributeWriter};
+use crate::digits::{DigitMode, DigitReader, DigitWriter};
+use crate::error::Result;
+use crate::manip::{self, Displacement, PackedVertex, VertexManipulator, VertexSet};
+use crate::mesh::{self, IndexedMesh};
+use crate::normals::{NormalReader, NormalWriter};
+use crate::permute::{self, PermutationReader, PermutationWriter};
+use crate::reader_writer::{BitFeed, ByteFeed};
+use crate::rotate::{self, RotationReader, RotationWriter};
+
+/*
+ *  A payload can be spread over more than one carrier. The bit stream fills them in a fixed
+ *  order: vertex bits, facet normals, attribute bytes, vertex rotation, triangle order and
+ *  then the printed digits of an ASCII file. Each one has a known capacity, so the reader knows
+ *  exactly where one ends and the next begins.
+ *
+ *  The carriers have to agree on how to walk the mesh: when triangles are permuted they are
+ *  walked in canonical order, and when they are rotated each one is walked from its canonical
+ *  start, both ignoring the bits the vertex channel writes.
+ *
+ *  Formats with their own indexed vertex list (PLY) only use the vertex channel, directly on
+ *  their positions in whatever precision the file has.
+ */
+// vertex channel bits are written and read this many at a time, the vertices of each chunk in
+// parallel (see VertexManipulator::write_bits)
+const VERTEX_CHUNK_BITS: usize = 1 << 18;
+
+#[derive(Clone, Copy, Default)]
+pub struct Carriers {
+    pub vertex_bits: Option<u8>,
+    pub normal_bits: Option<u8>,
+    pub attributes: Option<AttributeMode>,
+    pub rotation: bool,
+    pub permutation: bool,
+    pub digits: Option<DigitMode>,
+}
+
+impl Carriers {
+    fn mask_bits(&self) -> u8 {
+        self.vertex_bits.unwrap_or(0)
+    }
+
+    fn normal_mask_bits(&self) -> u8 {
+        self.normal_bits.unwrap_or(0)
+    }
+
+    fn check_bit_depths(&self) -> Result<()> {
+        // both are masked out of f32 triangles, before any manipulator gets to check its own
+        if let Some(bits) = self.vertex_bits {
+            manip::check_bit_depth(bits, 32)?;
+        }
+        if let Some(bits) = self.normal_bits {
+            manip::check_bit_depth(bits, 32)?;
+        }
+        Ok(())
+    }
+
+    fn whole_triangles(&self, mesh: &IndexedMesh) -> Vec<stl::Triangle> {
+        // only the carriers past the vertex channel look at whole triangles, it alone gets none
+        let needed = self.normal_bits.is_some()
+            || self.attributes.is_some()
+            || self.rotation
+            || self.permutation
+            || self.digits.is_some();
+        if needed {
+            mesh.to_triangles()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn triangle_order(&self, triangles: &[stl::Triangle], mesh: &IndexedMesh) -> Vec<usize> {
+        if self.permutation {
+            permute::canonical_indices(triangles, self.mask_bits())
+        } else {
+            mesh::file_order(mesh.triangles.len())
+        }
+    }
+
+    fn sorted_triangles(
+        &self,
+        triangles: &[stl::Triangle],
+        mesh: &IndexedMesh,
+    ) -> Vec<stl::Triangle> {
+        self.triangle_order(triangles, mesh)
+            .iter()
+            .map(|i| manip::copy_triangle(&triangles[*i]))
+            .collect()
+    }
+
+    pub fn vertex_order(&self, mesh: &IndexedMesh) -> Vec<u32> {
+        self.walk_order(&self.whole_triangles(mesh), mesh)
+    }
+
+    fn walk_order(&self, triangles: &[stl::Triangle], mesh: &IndexedMesh) -> Vec<u32> {
+        // the mesh vertices as first met walking the canonical triangles from their canonical starts
+        let starts = if self.rotation {
+            rotate::canonical_starts(&self.sorted_triangles(triangles, mesh), self.mask_bits())
+        } else {
+            Vec::<u8>::new()
+        };
+        mesh.first_seen_order(&self.triangle_order(triangles, mesh), &starts)
+    }
+
+    fn vertices(&self, order: &[u32], mesh: &IndexedMesh) -> Vec<[u32; 3]> {
+        order
+            .iter()
+            .map(|i| mesh.vertices[*i as usize].map(f32::to_bits))
+            .collect()
+    }
+
+    pub fn writer<F>(&self, mesh: &IndexedMesh, make_manipulator: F) -> Result<CarrierWriter>
+    where
+        F: FnOnce(Vec<[u32; 3]>, u8) -> Result<VertexManipulator<[u32; 3]>>,
+    {
+        // make_manipulator gets the vertex list in carrier order, to apply any keyed scattering
+        self.check_bit_depths()?;
+        let triangles = self.whole_triangles(mesh);
+        let triangles = &triangles[..];
+        let mut writer = CarrierWriter::empty(*self);
+        if let Some(bits) = self.vertex_bits {
+            let order = self.walk_order(triangles, mesh);
+            let uniq_vertices = self.vertices(&order, mesh);
+            writer.vertex_capacity = manip::get_available_bits(bits, &uniq_vertices);
+            writer.vertex_order = Some(order);
+            writer.vertices = Some(make_manipulator(uniq_vertices, bits)?.into());
+        }
+        if let Some(bits) = self.normal_bits {
+            let sorted = self.sorted_triangles(triangles, mesh);
+            writer.normals = Some(NormalWriter::new(&sorted, bits));
+        }
+        if let Some(mode) = self.attributes {
+            let sorted = self.sorted_triangles(triangles, mesh);
+            writer.attributes = Some(AttributeWriter::new(&sorted, mode));
+        }
+        if self.rotation {
+            writer.rotation = Some(RotationWriter::new(triangles, self.mask_bits()));
+        }
+        if self.permutation {
+            writer.permutation = Some(PermutationWriter::new(triangles, self.mask_bits()));
+        }
+        if let Some(mode) = self.digits {
+            writer.digits = Some(DigitWriter::new(
+                triangles,
+                mode,
+                self.mask_bits(),
+                self.normal_mask_bits(),
+            ));
+        }
+        Ok(writer)
+    }
+
+    pub fn reader<F>(&self, mesh: &IndexedMesh, make_manipulator: F) -> Result<CarrierReader>
+    where
+        F: FnOnce(Vec<[u32; 3]>, u8) -> Result<VertexManipulator<[u32; 3]>>,
+    {
+        self.check_bit_depths()?;
+        let triangles = self.whole_triangles(mesh);
+        let triangles = &triangles[..];
+        let mut reader = CarrierReader::empty();
+        if let Some(bits) = self.vertex_bits {
+            let uniq_vertices = self.vertices(&self.walk_order(triangles, mesh), mesh);
+            reader.vertex_capacity = manip::get_available_bits(bits, &uniq_vertices);
+            reader.vertices = Some(make_manipulator(uniq_vertices, bits)?.into());
+        }
+        if let Some(bits) = self.normal_bits {
+            let sorted = self.sorted_triangles(triangles, mesh);
+            reader.normals = Some(NormalReader::new(&sorted, bits));
+        }
+        if let Some(mode) = self.attributes {
+            let sorted = self.sorted_triangles(triangles, mesh);
+            reader.attributes = Some(AttributeReader::new(&sorted, mode));
+        }
+        if self.rotation {
+            let sorted = self.sorted_triangles(triangles, mesh);
+            reader.rotation = Some(RotationReader::new(&sorted, self.mask_bits()));
+        }
+        if self.permutation {
+            reader.permutation = Some(PermutationReader::new(triangles, self.mask_bits()));
+        }
+        Ok(reader)
+    }
+
+    pub fn text_reader<F>(
+        &self,
+        mesh: &IndexedMesh,
+        text: &[u8],
+        make_manipulator: F,
+    ) -> Result<CarrierReader>
+    where
+        F: FnOnce(Vec<[u32; 3]>, u8) -> Result<VertexManipulator<[u32; 3]>>,
+    {
+        // the digits carrier is read from the printed numbers, everything else from the values
+        let mut reader = self.reader(mesh, make_manipulator)?;
+        if let Some(mode) = self.digits {
+            reader.digits = Some(DigitReader::new(
+                text,
+                mode,
+                self.mask_bits(),
+                self.normal_mask_bits(),
+            )?);
+        }
+        Ok(reader)
+    }
+
+    pub fn position_writer<V, F>(
+        &self,
+        uniq_vertices: Vec<V>,
+        make_manipulator: F,
+    ) -> Result<CarrierWriter>
+    where
+        V: PackedVertex,
+        F: FnOnce(Vec<V>, u8) -> Result<VertexManipulator<V>>,
+        VertexChannel: From<VertexManipulator<V>>,
+    {
+        // only the vertex channel, on positions that aren't STL triangles
+        let mut writer = CarrierWriter::empty(*self);
+        if let Some(bits) = self.vertex_bits {
+            writer.vertex_capacity = manip::get_available_bits(bits, &uniq_vertices);
+            writer.original_positions = Some(V::into_set(uniq_vertices.clone()));
+            writer.vertices = Some(make_manipulator(uniq_vertices, bits)?.into());
+        }
+        Ok(writer)
+    }
+
+    pub fn position_reader<V, F>(
+        &self,
+        uniq_vertices: Vec<V>,
+        make_manipulator: F,
+    ) -> Result<CarrierReader>
+    where
+        V: PackedVertex,
+        F: FnOnce(Vec<V>, u8) -> Result<VertexManipulator<V>>,
+        VertexChannel: From<VertexManipulator<V>>,
+    {
+        let mut reader = CarrierReader::empty();
+        if let Some(bits) = self.vertex_bits {
+            reader.vertex_capacity = manip::get_available_bits(bits, &uniq_vertices);
+            reader.vertices = Some(make_manipulator(uniq_vertices, bits)?.into());
+        }
+        Ok(reader)
+    }
+}
+
+pub enum VertexChannel {
+    Single(VertexManipulator<[u32; 3]>),
+    Double(VertexManipulator<[u64; 3]>),
+}
+
+impl VertexChannel {
+    fn into_vertices(self) -> VertexSet {
+        match self {
+            VertexChannel::Single(vman) => VertexSet::Single(vman.into_vertices()),
+            VertexChannel::Double(vman) => VertexSet::Double(vman.into_vertices()),
+        }
+    }
+}
+
+impl From<VertexManipulator<[u32; 3]>> for VertexChannel {
+    fn from(vman: VertexManipulator<[u32; 3]>) -> VertexChannel {
+        VertexChannel::Single(vman)
+    }
+}
+
+impl From<VertexManipulator<[u64; 3]>> for VertexChannel {
+    fn from(vman: VertexManipulator<[u64; 3]>) -> VertexChannel {
+        VertexChannel::Double(vman)
+    }
+}
+
+pub struct Embedded {
+    pub stl: stl::BinaryStlFile,
+    pub digits: Option<DigitWriter>,
+    // what a position writer wrote, in the order its positions were given
+    pub positions: Option<VertexSet>,
+    pub displacement: Displacement,
+}
+
+pub struct CarrierWriter {
+    carriers: Carriers,
+    vertices: Option<VertexChannel>,
+    vertex_capacity: u64,
+    vertex_bits_used: u64,
+    pending_vertex_bits: Vec<u8>,
+    // which mesh vertex each one in the vertex channel is, none for a position writer
+    vertex_order: Option<Vec<u32>>,
+    // a position writer's positions as given, to measure how far they moved
+    original_positions: Option<VertexSet>,
+    normals: Option<NormalWriter>,
+    attributes: Option<AttributeWriter>,
+    rotation: Option<RotationWriter>,
+    permutation: Option<PermutationWriter>,
+    digits: Option<DigitWriter>,
+}
+
+impl CarrierWriter {
+    fn empty(carriers: Carriers) -> CarrierWriter {
+        CarrierWriter {
+            carriers,
+            vertices: None,
+            vertex_capacity: 0,
+            vertex_bits_used: 0,
+            pending_vertex_bits: Vec::<u8>::new(),
+            vertex_order: None,
+            original_positions: None,
+            normals: None,
+            attributes: None,
+            rotation: None,
+            permutation: None,
+            digits: None,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        let mut capacity = self.vertex_capacity;
+        if let Some(normals) = &self.normals {
+            capacity += normals.capacity_bits();
+        }
+        if let Some(attributes) = &self.attributes {
+            capacity += attributes.capacity_bits();
+        }
+        if let Some(rotation) = &self.rotation {
+            capacity += rotation.capacity_bits();
+        }
+        if let Some(permutation) = &self.permutation {
+            capacity += permutation.capacity_bits();
+        }
+        if let Some(digits) = &self.digits {
+            capacity += digits.capacity_bits();
+        }
+        capacity
+    }
+
+    pub fn write_bit(&mut self, bit: u8) {
+        if self.vertex_bits_used < self.vertex_capacity && self.vertices.is_some() {
+            self.pending_vertex_bits.push(bit);
+            self.vertex_bits_used += 1;
+            if self.pending_vertex_bits.len() == VERTEX_CHUNK_BITS {
+                self.flush_vertex_bits();
+            }
+            return;
+        }
+        if let Some(normals) = self.normals.as_mut()
+            && normals.pushed_bits() < normals.capacity_bits()
+        {
+            normals.push_bit(bit);
+            return;
+        }
+        if let Some(attributes) = self.attributes.as_mut()
+            && attributes.pushed_bits() < attributes.capacity_bits()
+        {
+            attributes.push_bit(bit);
+            return;
+        }
+        if let Some(rotation) = self.rotation.as_mut()
+            && rotation.pushed_bits() < rotation.capacity_bits()
+        {
+            rotation.push_bit(bit);
+            return;
+        }
+        if let Some(permutation) = self.permutation.as_mut()
+            && permutation.pushed_bits() < permutation.capacity_bits()
+        {
+            permutation.push_bit(bit);
+            return;
+        }
+        if let Some(digits) = self.digits.as_mut() {
+            digits.push_bit(bit);
+        }
+    }
+
+    pub fn write_feed(&mut self, feed: &mut impl BitFeed) -> Result<()> {
+        // every bit the feed has left, in order
+        while !feed.done() {
+            self.write_bit(feed.get_bit()?);
+        }
+        Ok(())
+    }
+
+    fn flush_vertex_bits(&mut self) {
+        match self.vertices.as_mut() {
+            Some(VertexChannel::Single(vman)) => vman.write_bits(&self.pending_vertex_bits),
+            Some(VertexChannel::Double(vman)) => vman.write_bits(&self.pending_vertex_bits),
+            None => {}
+        }
+        self.pending_vertex_bits.clear();
+    }
+
+    pub fn finish(mut self, header: &stl::BinaryStlHeader, mesh: &IndexedMesh) -> Embedded {
+        // any digits only exist once the file is printed, stlio::write_embedded_stl does that
+        // and double precision positions only in their own file, stlio::write_mesh does both
+        // mesh has to be the one the carriers were set up on
+        self.flush_vertex_bits();
+        let triangles = self.carriers.whole_triangles(mesh);
+        let triangle_order = self.carriers.triangle_order(&triangles, mesh);
+        //rotations are counted from the canonical start, in the order the reader will see them
+        let rotations = match &self.rotation {
+            Some(rotation) => {
+                let canonical = self.carriers.sorted_triangles(&triangles, mesh);
+                rotation.rotations(&canonical)
+            }
+            None => Vec::<u8>::new(),
+        };
+        // done with the whole triangles before the written ones are built
+        drop(triangles);
+
+        let written = self.vertices.map(VertexChannel::into_vertices);
+        let mut vertices = mesh.vertices.clone();
+        let mut displacement = Displacement::default();
+        let positions = match (&self.vertex_order, written) {
+            (Some(order), Some(VertexSet::Single(written))) => {
+                displacement = manip::displacement(&self.carriers.vertices(order, mesh), &written);
+                for (index, vertex) in order.iter().zip(written.iter()) {
+                    vertices[*index as usize] = vertex.map(f32::from_bits);
+                }
+                None
+            }
+            (_, written) => {
+                if let (Some(before), Some(after)) = (&self.original_positions, &written) {
+                    displacement = before.displacement(after);
+                }
+                written
+            }
+        };
+
+        let mut outstl = stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: header.header,
+                num_triangles: header.num_triangles,
+            },
+            triangles: mesh.write_triangles(&vertices, &triangle_order, &rotations),
+        };
+        if let Some(normals) = &self.normals {
+            normals.apply(&mut outstl.triangles);
+        }
+        if let Some(attributes) = &self.attributes {
+            attributes.apply(&mut outstl.triangles);
+        }
+        if let Some(permutation) = self.permutation {
+            outstl.triangles = permutation.finish(&outstl.triangles);
+        }
+        Embedded {
+            stl: outstl,
+            digits: self.digits,
+            positions,
+            displacement,
+        }
+    }
+}
+
+pub struct CarrierReader {
+    vertices: Option<VertexChannel>,
+    vertex_capacity: u64,
+    vertex_bits_used: u64,
+    vertex_bits_read: Vec<u8>,
+    vertex_bits_read_pos: usize,
+    normals: Option<NormalReader>,
+    attributes: Option<AttributeReader>,
+    rotation: Option<RotationReader>,
+    permutation: Option<PermutationReader>,
+    digits: Option<DigitReader>,
+}
+
+impl CarrierReader {
+    fn empty() -> CarrierReader {
+        CarrierReader {
+            vertices: None,
+            vertex_capacity: 0,
+            vertex_bits_used: 0,
+            vertex_bits_read: Vec::<u8>::new(),
+            vertex_bits_read_pos: 0,
+            normals: None,
+            attributes: None,
+            rotation: None,
+            permutation: None,
+            digits: None,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        let mut capacity = self.vertex_capacity;
+        if let Some(normals) = &self.normals {
+            capacity += normals.capacity_bits();
+        }
+        if let Some(attributes) = &self.attributes {
+            capacity += attributes.capacity_bits();
+        }
+        if let Some(rotation) = &self.rotation {
+            capacity += rotation.capacity_bits();
+        }
+        if let Some(permutation) = &self.permutation {
+            capacity += permutation.capacity_bits();
+        }
+        if let Some(digits) = &self.digits {
+            capacity += digits.capacity_bits();
+        }
+        capacity
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        if self.vertex_bits_used < self.vertex_capacity
+            && let Some(channel) = self.vertices.as_mut()
+        {
+            if self.vertex_bits_read_pos == self.vertex_bits_read.len() {
+                let remaining = self.vertex_capacity - self.vertex_bits_used;
+                let count = remaining.min(VERTEX_CHUNK_BITS as u64) as usize;
+                self.vertex_bits_read = match channel {
+                    VertexChannel::Single(vman) => vman.read_bits(count),
+                    VertexChannel::Double(vman) => vman.read_bits(count),
+                };
+                self.vertex_bits_read_pos = 0;
+            }
+            self.vertex_bits_used += 1;
+            self.vertex_bits_read_pos += 1;
+            return self.vertex_bits_read[self.vertex_bits_read_pos - 1];
+        }
+        if let Some(normals) = self.normals.as_mut()
+            && normals.read_bits() < normals.capacity_bits()
+        {
+            return normals.next_bit();
+        }
+        if let Some(attributes) = self.attributes.as_mut()
+            && attributes.read_bits() < attributes.capacity_bits()
+        {
+            return attributes.next_bit();
+        }
+        if let Some(rotation) = self.rotation.as_mut()
+            && rotation.read_bits() < rotation.capacity_bits()
+        {
+            return rotation.next_bit();
+        }
+        if let Some(permutation) = self.permutation.as_mut()
+            && permutation.read_bits() < permutation.capacity_bits()
+        {
+            return permutation.next_bit();
+        }
+        match self.digits.as_mut() {
+            Some(digits) => digits.next_bit(),
+            None => 0,
+        }
+    }
+
+    pub fn read_bytes(&mut self, decoder: &mut impl ByteFeed, count: u64) -> Vec<u8> {
+        // exactly count bytes through decoder, past the end of every carrier they come out 0
+        let mut output = Vec::<u8>::new();
+        while (output.len() as u64) < count {
+            decoder.push_bit(self.next_bit());
+            if decoder.bytes_available() > 0 {
+                output.push(decoder.get_byte());
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manip::ManipulatorMode;
+    use crate::stlio;
+
+    fn mesh() -> stl::BinaryStlFile {
+        let f = |n: u32| (n as f32) * 1.5 + 0.25;
+        let triangles: Vec<stl::Triangle> = (0..64)
+            .map(|i| stl::Triangle {
+                normal: [0.0, 0.0, 1.0],
+                v1: [f(i), f(i + 1), f(i + 2)],
+                v2: [f(i + 3), f(i), f(i + 7)],
+                v3: [f(i + 1), f(i + 5), f(i)],
+                attr_byte_count: 0,
+            })
+            .collect();
+        stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: [0u8; 80],
+                num_triangles: triangles.len() as u32,
+            },
+            triangles,
+        }
+    }
+
+    #[test]
+    fn test_chained_roundtrip() {
+        let stl = mesh();
+        let carriers = Carriers {
+            vertex_bits: Some(2),
+            normal_bits: Some(3),
+            attributes: Some(AttributeMode::Color(1)),
+            rotation: true,
+            permutation: true,
+            digits: None,
+        };
+        let indexed = IndexedMesh::from_triangles(&stl.triangles);
+        let mut writer = carriers
+            .writer(&indexed, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+            })
+            .unwrap();
+
+        // enough to run through every other carrier and into the triangle order
+        let vertex_bits = manip::get_available_bits(2, &carriers.vertex_order(&indexed));
+        let payload: Vec<u8> = (0..vertex_bits + 64 * 9 + 64 * 3 + 5 * 19 + 100)
+            .map(|i| ((i * 5) % 7 < 3) as u8)
+            .collect();
+        assert!(writer.capacity_bits() >= payload.len() as u64);
+        for bit in payload.iter() {
+            writer.write_bit(*bit);
+        }
+        let encoded = writer.finish(&stl.header, &indexed).stl;
+
+        let encoded_mesh = IndexedMesh::from_triangles(&encoded.triangles);
+        let mut reader = carriers
+            .reader(&encoded_mesh, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::READ, v, bits)
+            })
+            .unwrap();
+        assert_eq!(reader.capacity_bits(), writer_capacity(&carriers, &stl));
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+
+    #[test]
+    fn test_digits_follow_permutation() {
+        let stl = mesh();
+        let carriers = Carriers {
+            vertex_bits: Some(3),
+            permutation: true,
+            digits: Some(DigitMode::new(2, true)),
+            ..Default::default()
+        };
+        let indexed = IndexedMesh::from_triangles(&stl.triangles);
+        let mut writer = carriers
+            .writer(&indexed, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+            })
+            .unwrap();
+        let capacity = writer.capacity_bits();
+        let payload: Vec<u8> = (0..capacity).map(|i| ((i * 3) % 5 < 2) as u8).collect();
+        for bit in payload.iter() {
+            writer.write_bit(*bit);
+        }
+        let embedded = writer.finish(&stl.header, &indexed);
+        let (encoded, mut digits) = (embedded.stl, embedded.digits.unwrap());
+        let mut text = Vec::<u8>::new();
+        stlio::write_ascii_stl_with(&mut text, &encoded, |v| digits.format(v)).unwrap();
+
+        let read = stlio::read_stl(&text).unwrap();
+        let read_mesh = IndexedMesh::from_triangles(&read.triangles);
+        let mut reader = carriers
+            .text_reader(&read_mesh, &text, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::READ, v, bits)
+            })
+            .unwrap();
+        assert_eq!(reader.capacity_bits(), capacity);
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+
+    #[test]
+    fn test_double_positions_roundtrip() {
+        let positions: Vec<[u64; 3]> = (0..30)
+            .map(|i| manip::get_double_position_as_bits(&[i as f64 / 3.0, -2.5, 1e6 + i as f64]))
+            .collect();
+        let carriers = Carriers {
+            vertex_bits: Some(40),
+            ..Default::default()
+        };
+        let mut writer = carriers
+            .position_writer(positions.clone(), |v, bits| {
+                VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+            })
+            .unwrap();
+        assert_eq!(writer.capacity_bits(), 30 * 3 * 40);
+        let payload: Vec<u8> = (0..3000).map(|i| ((i * 5) % 7 < 3) as u8).collect();
+        for bit in payload.iter() {
+            writer.write_bit(*bit);
+        }
+        let stl = mesh();
+        let encoded = match writer.finish(&stl.header, &IndexedMesh::from_triangles(&stl.triangles))
+        {
+            Embedded {
+                positions: Some(VertexSet::Double(encoded)),
+                ..
+            } => encoded,
+            _ => panic!("double positions didn't come back as written"),
+        };
+
+        let mut reader = carriers
+            .position_reader(encoded, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::READ, v, bits)
+            })
+            .unwrap();
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+
+        // 40 bits only fit in doubles, STL triangles are refused
+        let indexed = IndexedMesh::from_triangles(&stl.triangles);
+        let refused = carriers.writer(&indexed, |v, bits| {
+            VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+        });
+        assert!(matches!(
+            refused,
+            Err(crate::error::StegaError::BitDepth { bits: 40, .. })
+        ));
+    }
+
+    fn writer_capacity(carriers: &Carriers, stl: &stl::BinaryStlFile) -> u64 {
+        let indexed = IndexedMesh::from_triangles(&stl.triangles);
+        carriers
+            .writer(&indexed, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+            })
+            .unwrap()
+            .capacity_bits()
+    }
+}
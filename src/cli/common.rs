@@ -0,0 +1,429 @@
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command, value_parser};
+
+use crate::embedder::{Embedder, Extractor, Framing, Keying, Stored};
+use crate::error::{Result, StegaError};
+use crate::mesh::IndexedMesh;
+use crate::{attributes, carrier, detect, digits, manip, reader_writer, stlio};
+
+/*
+ *  What the data and text commands share: their options, and loading a mesh and setting up
+ *  the carriers from them. Every option is looked up by its id, so a tool can take it as a
+ *  positional argument and stegastl as a named one.
+ */
+pub fn quiet_arg() -> Arg {
+    Arg::new("quiet")
+        .long("quiet")
+        .short('q')
+        .global(true)
+        .action(ArgAction::SetTrue)
+}
+
+pub fn json_arg() -> Arg {
+    //one JSON document on stdout in place of the report, see json.rs
+    Arg::new("json").long("json").action(ArgAction::SetTrue)
+}
+
+pub fn bits_arg() -> Arg {
+    Arg::new("bits")
+        .required(false)
+        .value_parser(value_parser!(u8).range(1..=64))
+}
+
+pub fn bits_option() -> Arg {
+    bits_arg().long("bits").short('b')
+}
+
+pub fn output_option() -> Arg {
+    Arg::new("out_file_path")
+        .long("output")
+        .short('o')
+        .required(true)
+}
+
+pub fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_parser(stlio::StlFormat::NAMES)
+        .default_value("binary")
+}
+
+pub fn embedder<'a>(
+    stl: &'a stl::BinaryStlFile,
+    secret: &Option<Vec<u8>>,
+    framing: Framing,
+    args: &ArgMatches,
+) -> Embedder<'a> {
+    //only seals and frames the payload, the carriers come from carrier_writer
+    let mut embedder = Embedder::new(stl).framing(framing);
+    if let Some(secret) = secret {
+        embedder = embedder.secret(secret);
+    }
+    if args.get_flag("compress") {
+        embedder = embedder.compress();
+    }
+    if let Some(parity) = args.get_one::<u8>("ecc") {
+        embedder = embedder.ecc(*parity);
+    }
+    embedder
+}
+
+pub fn extractor<'a>(
+    stl: &'a stl::BinaryStlFile,
+    secret: &Option<Vec<u8>>,
+    framing: Framing,
+) -> Extractor<'a> {
+    //only reads and opens the payload, the carriers come from carrier_reader
+    let extractor = Extractor::new(stl).framing(framing);
+    match secret {
+        Some(secret) => extractor.secret(secret),
+        None => extractor,
+    }
+}
+
+pub fn say_stored(stored: &Stored, args: &ArgMatches) {
+    match (&stored.header, stored.framing) {
+        (Some(header), _) => {
+            say!(
+                args,
+                "Frame read, payload bytes: {}",
+                header.payload_bytes()
+            );
+            if stored.corrected > 0 {
+                say!(args, "Corrected {} damaged bytes", stored.corrected);
+            }
+            say!(args, "Checksum OK");
+        }
+        (None, Framing::Legacy) => {
+            say!(
+                args,
+                "Header read, payload bytes: {}",
+                stored.payload_bytes()
+            )
+        }
+        (None, Framing::Headerless) if stored.encrypted => say!(
+            args,
+            "Sealed length read, payload bytes: {}",
+            stored.payload_bytes()
+        ),
+        _ => {}
+    }
+}
+
+pub fn read_bits(
+    indexed: &IndexedMesh,
+    mesh: &stlio::MeshSource,
+    text: &[u8],
+    seed: Option<[u8; 32]>,
+    framed: bool,
+    args: &ArgMatches,
+) -> Result<Option<u8>> {
+    //the depth given, or else the one a frame is found at
+    if !uses_carrier(args, "vertices") {
+        return Ok(None);
+    }
+    if let Some(bits) = args.get_one::<u8>("bits") {
+        return Ok(Some(*bits));
+    }
+    if !framed {
+        return Err(StegaError::BitDepthRequired);
+    }
+    let reader = |bits| carrier_reader(indexed, mesh, text, Some(bits), seed, args);
+    match detect::detect_bit_depth(mesh.component_bits(), reader)? {
+        Some(bits) => {
+            say!(args, "Detected bit depth: {}", bits);
+            Ok(Some(bits))
+        }
+        None => Err(reader_writer::FrameError::NoPayload.into()),
+    }
+}
+
+pub fn with_key_args(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new("passphrase")
+            .long("passphrase")
+            .conflicts_with("key_file"),
+    )
+    .arg(Arg::new("key_file").long("key-file"))
+    .group(ArgGroup::new("key").args(["passphrase", "key_file"]))
+}
+
+pub fn with_scatter_args(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new("scatter")
+            .long("scatter")
+            .action(ArgAction::SetTrue)
+            .requires("key"),
+    )
+    .arg(
+        Arg::new("scatter_bits")
+            .long("scatter-bits")
+            .action(ArgAction::SetTrue)
+            .requires("key"),
+    )
+}
+
+fn keying(secret: &Option<Vec<u8>>, args: &ArgMatches) -> Keying {
+    Keying {
+        secret: secret.clone(),
+        scatter: args.get_flag("scatter"),
+        scatter_bits: args.get_flag("scatter_bits"),
+    }
+}
+
+pub fn scatter_seed(secret: &Option<Vec<u8>>, args: &ArgMatches) -> Result<Option<[u8; 32]>> {
+    keying(secret, args).seed()
+}
+
+pub fn with_carrier_args(cmd: Command) -> Command {
+    cmd.arg(
+        Arg::new("carrier")
+            .long("carrier")
+            .value_delimiter(',')
+            .value_parser([
+                "vertices",
+                "normals",
+                "attributes",
+                "rotation",
+                "permutation",
+                "digits",
+            ])
+            .default_value("vertices"),
+    )
+    .arg(
+        Arg::new("normal_bits")
+            .long("normal-bits")
+            .value_parser(value_parser!(u8).range(1..=32))
+            .default_value("32"),
+    )
+    .arg(
+        Arg::new("attr_color_bits")
+            .long("attr-color-bits")
+            .value_parser(value_parser!(u8).range(1..=5)),
+    )
+    .arg(
+        Arg::new("digits")
+            .long("digits")
+            .value_parser(value_parser!(u8).range(1..=9))
+            .default_value("2"),
+    )
+    .arg(
+        Arg::new("digit_exponent")
+            .long("digit-exponent")
+            .action(ArgAction::SetTrue),
+    )
+    .arg(Arg::new("stream").long("stream").action(ArgAction::SetTrue))
+}
+
+pub fn load_mesh(
+    in_file_path: String,
+    args: &ArgMatches,
+) -> Result<(
+    stl::BinaryStlFile,
+    IndexedMesh,
+    stlio::MeshSource,
+    stlio::MeshStats,
+)> {
+    //streaming keeps only the unique vertices of a binary STL in memory, see stream.rs
+    let (stl, indexed, source, stats) = if args.get_flag("stream") {
+        stlio::stream_mesh(&in_file_path)?
+    } else {
+        stlio::load_indexed_mesh(&in_file_path)?
+    };
+    say!(args, "{}", stats);
+    Ok((stl, indexed, source, stats))
+}
+
+pub fn uses_carrier(args: &ArgMatches, name: &str) -> bool {
+    args.get_many::<String>("carrier")
+        .map(|mut carriers| carriers.any(|c| c == name))
+        .unwrap_or(false)
+}
+
+pub fn vertex_bits(args: &ArgMatches) -> Result<Option<u8>> {
+    match args.get_one::<u8>("bits") {
+        _ if !uses_carrier(args, "vertices") => Ok(None),
+        Some(bits) => Ok(Some(*bits)),
+        None => Err(StegaError::BitDepthRequired),
+    }
+}
+
+pub fn carrier_label(bits: Option<u8>, args: &ArgMatches) -> String {
+    let mut parts = Vec::<String>::new();
+    if uses_carrier(args, "vertices") {
+        parts.push(format!("{}-bit", bits.unwrap_or(0)));
+    }
+    if uses_carrier(args, "normals") {
+        let normal_bits = *args.get_one::<u8>("normal_bits").unwrap();
+        parts.push(format!("{}-bit normals", normal_bits));
+    }
+    if uses_carrier(args, "attributes") {
+        parts.push("attributes".to_string());
+    }
+    if uses_carrier(args, "rotation") {
+        parts.push("rotation".to_string());
+    }
+    if uses_carrier(args, "permutation") {
+        parts.push("permutation".to_string());
+    }
+    if uses_carrier(args, "digits") {
+        let digits = *args.get_one::<u8>("digits").unwrap();
+        parts.push(format!("{}-digit", digits));
+    }
+    parts.join(" + ")
+}
+
+pub fn carriers(bits: Option<u8>, args: &ArgMatches) -> carrier::Carriers {
+    carrier::Carriers {
+        vertex_bits: bits,
+        normal_bits: match uses_carrier(args, "normals") {
+            true => args.get_one::<u8>("normal_bits").copied(),
+            false => None,
+        },
+        attributes: match uses_carrier(args, "attributes") {
+            true => Some(attribute_mode(args)),
+            false => None,
+        },
+        rotation: uses_carrier(args, "rotation"),
+        permutation: uses_carrier(args, "permutation"),
+        digits: match uses_carrier(args, "digits") {
+            true => Some(digits::DigitMode::new(
+                *args.get_one::<u8>("digits").unwrap(),
+                args.get_flag("digit_exponent"),
+            )),
+            false => None,
+        },
+    }
+}
+
+pub fn attribute_mode(args: &ArgMatches) -> attributes::AttributeMode {
+    match args.get_one::<u8>("attr_color_bits") {
+        Some(bits) => attributes::AttributeMode::Color(*bits),
+        None => attributes::AttributeMode::Raw,
+    }
+}
+
+pub fn check_attributes(indexed: &IndexedMesh, args: &ArgMatches) -> Result<()> {
+    //raw mode would wipe out any facet colors, color mode builds on top of them
+    if !uses_carrier(args, "attributes") || attribute_mode(args) != attributes::AttributeMode::Raw {
+        return Ok(());
+    }
+    let usage = attributes::usage(&indexed.to_triangles());
+    if usage.in_use > 0 {
+        return Err(StegaError::CarrierUnavailable(format!(
+            "{} triangles already use their attribute bytes ({} distinct values), supply --attr-color-bits to keep them",
+            usage.in_use, usage.distinct
+        )));
+    }
+    Ok(())
+}
+
+pub fn output_format(args: &ArgMatches) -> Result<stlio::StlFormat> {
+    let format = stlio::StlFormat::from_name(args.get_one::<String>("format").unwrap()).unwrap();
+    let usage = |reason: &str| Err(StegaError::Usage(reason.to_string()));
+    if format == stlio::StlFormat::Ascii && uses_carrier(args, "attributes") {
+        return usage("ASCII STL has no attribute bytes to carry data");
+    }
+    if format == stlio::StlFormat::Ascii && args.get_flag("stream") {
+        return usage("a streamed STL is written back as binary, --stream can't change its format");
+    }
+    if format == stlio::StlFormat::Binary && uses_carrier(args, "digits") {
+        return usage("the digits carrier is printed text, supply --format ascii");
+    }
+    Ok(format)
+}
+
+pub fn carrier_writer(
+    indexed: &IndexedMesh,
+    mesh: &stlio::MeshSource,
+    bits: Option<u8>,
+    seed: Option<[u8; 32]>,
+    args: &ArgMatches,
+) -> Result<carrier::CarrierWriter> {
+    let carriers = carriers(bits, args);
+    let mode = || manip::ManipulatorMode::WRITE;
+    // the seed is derived already, only what it scatters is left to know
+    let scattering = keying(&None, args);
+    //a PLY carries on its own positions, in their own precision, a streamed STL has nothing else
+    match mesh {
+        stlio::MeshSource::Ply(_) | stlio::MeshSource::Streamed(_) => {
+            match mesh.vertices(indexed) {
+                manip::VertexSet::Single(uniq_vertices) => carriers
+                    .position_writer(uniq_vertices, |v, bits| {
+                        scattering.manipulator(mode(), v, bits, seed)
+                    }),
+                manip::VertexSet::Double(uniq_vertices) => carriers
+                    .position_writer(uniq_vertices, |v, bits| {
+                        scattering.manipulator(mode(), v, bits, seed)
+                    }),
+            }
+        }
+        _ => carriers.writer(indexed, |v, bits| {
+            scattering.manipulator(mode(), v, bits, seed)
+        }),
+    }
+}
+
+pub fn check_source(source: &stlio::MeshSource, args: &ArgMatches) -> Result<()> {
+    //only an STL has facet normals and attribute bytes, other meshes keep their faces as they were
+    let others = ["normals", "attributes", "rotation", "permutation", "digits"];
+    if !source.is_stl() && others.iter().any(|c| uses_carrier(args, c)) {
+        let reason = match source {
+            stlio::MeshSource::Streamed(_) => {
+                "only the vertices carrier is available when streaming".to_string()
+            }
+            _ => format!(
+                "only the vertices carrier is available in {} files",
+                source.extension().to_uppercase()
+            ),
+        };
+        return Err(StegaError::CarrierUnavailable(reason));
+    }
+    if let Some(bits) = args.get_one::<u8>("bits") {
+        manip::check_bit_depth(*bits, source.component_bits())?;
+    }
+    Ok(())
+}
+
+pub fn carrier_text(in_file_path: &str, args: &ArgMatches) -> Result<Vec<u8>> {
+    //only the digits carrier reads the file as text, the rest is in the parsed values
+    if !uses_carrier(args, "digits") {
+        return Ok(Vec::<u8>::new());
+    }
+    let text = std::fs::read(in_file_path)?;
+    if stlio::StlFormat::detect(&text) != stlio::StlFormat::Ascii {
+        return Err(StegaError::CarrierUnavailable(
+            "the digits carrier is only found in ASCII STL files".to_string(),
+        ));
+    }
+    Ok(text)
+}
+
+pub fn carrier_reader(
+    indexed: &IndexedMesh,
+    mesh: &stlio::MeshSource,
+    text: &[u8],
+    bits: Option<u8>,
+    seed: Option<[u8; 32]>,
+    args: &ArgMatches,
+) -> Result<carrier::CarrierReader> {
+    let carriers = carriers(bits, args);
+    let mode = || manip::ManipulatorMode::READ;
+    let scattering = keying(&None, args);
+    match mesh {
+        stlio::MeshSource::Ply(_) | stlio::MeshSource::Streamed(_) => {
+            match mesh.vertices(indexed) {
+                manip::VertexSet::Single(uniq_vertices) => carriers
+                    .position_reader(uniq_vertices, |v, bits| {
+                        scattering.manipulator(mode(), v, bits, seed)
+                    }),
+                manip::VertexSet::Double(uniq_vertices) => carriers
+                    .position_reader(uniq_vertices, |v, bits| {
+                        scattering.manipulator(mode(), v, bits, seed)
+                    }),
+            }
+        }
+        _ => carriers.text_reader(indexed, text, |v, bits| {
+            scattering.manipulator(mode(), v, bits, seed)
+        }),
+    }
+}
@@ -0,0 +1,340 @@
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use std::io;
+use std::io::Write;
+
+use super::common::*;
+use super::json::{self, Json};
+use crate::embedder::Framing;
+use crate::error::{Result, StegaError};
+use crate::{crypto, stlio};
+
+/*
+ *  Any file in and out of a mesh: encode and decode for the data tool, embed, extract and
+ *  verify for stegastl.
+ */
+fn with_encode_args(cmd: Command) -> Command {
+    with_carrier_args(with_scatter_args(with_key_args(
+        cmd.arg(
+            Arg::new("headerless")
+                .long("headerless")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("legacy")
+                .long("legacy")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("headerless"),
+        )
+        .arg(
+            Arg::new("ecc")
+                .long("ecc")
+                .value_parser(value_parser!(u8).range(2..=128))
+                .conflicts_with_all(["headerless", "legacy"]),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["headerless", "legacy"]),
+        )
+        .arg(format_arg())
+        .arg(json_arg()),
+    )))
+}
+
+fn with_decode_args(cmd: Command) -> Command {
+    with_carrier_args(with_scatter_args(with_key_args(
+        cmd.arg(
+            Arg::new("headerless")
+                .long("headerless")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("legacy")
+                .long("legacy")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("headerless"),
+        )
+        .arg(
+            Arg::new("length")
+                .long("length")
+                .value_parser(value_parser!(u64))
+                .requires("headerless")
+                .conflicts_with("key"),
+        )
+        .arg(json_arg()),
+    )))
+}
+
+pub fn command() -> Command {
+    Command::new("StegaSTL test tool: Data Embedding")
+        .subcommand(with_encode_args(
+            Command::new("encode")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(Arg::new("out_file_path").required(true))
+                .arg(Arg::new("data_file_path").required(true))
+                .arg(bits_arg()),
+        ))
+        .subcommand(with_decode_args(
+            Command::new("decode")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(Arg::new("out_file_path").required(true))
+                .arg(bits_arg()),
+        ))
+        .arg(quiet_arg())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("encode", sub_m)) => encode(sub_m),
+        Some(("decode", sub_m)) => decode(sub_m),
+        _ => Err(io::Error::other("Unknown command").into()),
+    }
+}
+
+pub fn embed_command() -> Command {
+    with_encode_args(
+        Command::new("embed")
+            .about("Embeds a file in a mesh")
+            .arg(Arg::new("in_file_path").required(true))
+            .arg(Arg::new("data_file_path").required(true))
+            .arg(output_option())
+            .arg(bits_option()),
+    )
+}
+
+pub fn extract_command() -> Command {
+    with_decode_args(
+        Command::new("extract")
+            .about("Extracts an embedded file, - writes it to stdout")
+            .arg(Arg::new("in_file_path").required(true))
+            .arg(output_option())
+            .arg(bits_option()),
+    )
+}
+
+pub fn verify_command() -> Command {
+    with_carrier_args(with_scatter_args(with_key_args(
+        Command::new("verify")
+            .about("Checks a mesh carries an intact framed payload, without writing it out")
+            .arg(Arg::new("in_file_path").required(true))
+            .arg(bits_option())
+            .arg(json_arg()),
+    )))
+}
+
+pub fn encode(args: &ArgMatches) -> Result<()> {
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let out_file_path: String = args.get_one::<String>("out_file_path").unwrap().clone();
+    let data_file_path: String = args.get_one::<String>("data_file_path").unwrap().clone();
+    let bits: Option<u8> = vertex_bits(args)?;
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+    let framing = framing(args);
+
+    let (stl, indexed, mut mesh, stats) = load_mesh(in_file_path, args)?;
+    check_source(&mesh, args)?;
+    check_attributes(&indexed, args)?;
+    let format = output_format(args)?;
+    let seed = scatter_seed(&secret, args)?;
+    let writer = carrier_writer(&indexed, &mesh, bits, seed, args)?;
+    let available_bytes: u64 = writer.capacity_bits() / 8;
+
+    let embedder = embedder(&stl, &secret, framing, args);
+    let sealed = embedder.seal(&std::fs::read(data_file_path)?)?;
+    if let Some(compressed_bytes) = sealed.compressed_bytes {
+        say!(
+            args,
+            "Payload compressed from {} to {} bytes",
+            sealed.input_bytes,
+            compressed_bytes
+        );
+    }
+    if secret.is_some() {
+        say!(args, "Payload encrypted");
+    }
+    let label = carrier_label(bits, args);
+    say!(
+        args,
+        "{} encoding {} bytes of data incl header",
+        label,
+        sealed.stored_bytes
+    );
+    say!(
+        args,
+        "{} storage provides {} bytes of stored data incl header",
+        label,
+        available_bytes
+    );
+    let (input_bytes, payload_bytes, stored_bytes) = (
+        sealed.input_bytes,
+        sealed.payload_bytes,
+        sealed.stored_bytes,
+    );
+    let embedded = embedder.write(sealed, writer, &indexed)?;
+    if framing == Framing::Headerless && secret.is_none() {
+        say!(
+            args,
+            "Headerless payload, decode with --headerless --length {}",
+            payload_bytes
+        );
+    }
+
+    let displacement = embedded.displacement;
+    say!(args, "Writing file {}", out_file_path);
+    stlio::write_mesh(out_file_path.to_string(), embedded, format, &mut mesh)?;
+    if args.get_flag("json") {
+        let report = Json::document("encode")
+            .with("mesh", json::mesh_stats(&stats))
+            .with("output", out_file_path)
+            .with("bits", bits)
+            .with("framing", framing.name())
+            .with("payload_bytes", input_bytes)
+            .with("bytes_written", stored_bytes)
+            .with("capacity_bytes", available_bytes)
+            .with("compressed", args.get_flag("compress"))
+            .with("encrypted", secret.is_some())
+            .with("vertices_touched", displacement.vertices_touched)
+            .with("max_displacement", displacement.max_distance);
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+fn framing(args: &ArgMatches) -> Framing {
+    if args.get_flag("legacy") {
+        Framing::Legacy
+    } else if args.get_flag("headerless") {
+        Framing::Headerless
+    } else {
+        Framing::Frame
+    }
+}
+
+pub fn decode(args: &ArgMatches) -> Result<()> {
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let out_file_path: String = args.get_one::<String>("out_file_path").unwrap().clone();
+    if out_file_path == "-" && args.get_flag("json") {
+        return Err(StegaError::Usage(
+            "--json needs an output file, stdout carries the report".to_string(),
+        ));
+    }
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+    let framing = framing(args);
+
+    let text = carrier_text(&in_file_path, args)?;
+    let (stl, indexed, mesh, stats) = load_mesh(in_file_path, args)?;
+    check_source(&mesh, args)?;
+    let seed = scatter_seed(&secret, args)?;
+    let bits = read_bits(
+        &indexed,
+        &mesh,
+        &text,
+        seed,
+        framing == Framing::Frame,
+        args,
+    )?;
+
+    let mut extractor = extractor(&stl, &secret, framing);
+    if let Some(length) = args.get_one::<u64>("length") {
+        extractor = extractor.length(*length);
+    }
+    let reader = carrier_reader(&indexed, &mesh, &text, bits, seed, args)?;
+    let stored = extractor.read(reader)?;
+    say_stored(&stored, args);
+    //only a frame has a checksum and flags, the other framings are taken as given
+    let checksum = if stored.header.is_some() {
+        "ok"
+    } else {
+        "none"
+    };
+    let (corrected, encrypted, compressed) =
+        (stored.corrected, stored.encrypted, stored.compressed);
+    let output = extractor.open(stored)?;
+
+    let payload_bytes = output.len() as u64;
+    write_decoded_file(&out_file_path, output, args)?;
+    say!(args, "Decode complete.");
+    if args.get_flag("json") {
+        let report = Json::document("decode")
+            .with("mesh", json::mesh_stats(&stats))
+            .with("bits", bits)
+            .with("framing", framing.name())
+            .with("payload_bytes", payload_bytes)
+            .with("checksum", checksum)
+            .with("corrected_bytes", corrected as u64)
+            .with("encrypted", encrypted)
+            .with("compressed", compressed)
+            .with("output", out_file_path);
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+fn write_decoded_file(out_file_path: &String, output: Vec<u8>, args: &ArgMatches) -> Result<()> {
+    //- is stdout, with --quiet nothing else goes there
+    if out_file_path == "-" {
+        io::stdout().write_all(&output)?;
+        return Ok(());
+    }
+    say!(
+        args,
+        "Writing {} bytes of data to output file {}",
+        output.len(),
+        out_file_path
+    );
+    stlio::write_binary_file(out_file_path.to_string(), output)
+}
+
+pub fn verify(args: &ArgMatches) -> Result<()> {
+    //the checksum is checked on reading the frame, a secret also has to open the payload
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+
+    let text = carrier_text(&in_file_path, args)?;
+    let (stl, indexed, mesh, stats) = load_mesh(in_file_path, args)?;
+    check_source(&mesh, args)?;
+    let seed = scatter_seed(&secret, args)?;
+    let bits = read_bits(&indexed, &mesh, &text, seed, true, args)?;
+
+    let extractor = extractor(&stl, &secret, Framing::Frame);
+    let reader = carrier_reader(&indexed, &mesh, &text, bits, seed, args)?;
+    let stored = extractor.read(reader)?;
+    say_stored(&stored, args);
+
+    let (stored_bytes, corrected) = (stored.payload_bytes(), stored.corrected);
+    let (encrypted, compressed) = (stored.encrypted, stored.compressed);
+    // a key that doesn't open the payload is an error, null is no key or nothing to open
+    let mut key_opens = None;
+    if encrypted {
+        match &secret {
+            Some(_) => {
+                extractor.open(stored)?;
+                say!(args, "Key opens the payload");
+                key_opens = Some(true);
+            }
+            None => say!(args, "Payload is encrypted"),
+        }
+    }
+    if args.get_flag("json") {
+        let report = Json::document("verify")
+            .with("mesh", json::mesh_stats(&stats))
+            .with("bits", bits)
+            .with("stored_payload_bytes", stored_bytes)
+            .with("checksum", "ok")
+            .with("corrected_bytes", corrected as u64)
+            .with("encrypted", encrypted)
+            .with("compressed", compressed)
+            .with("key_opens", key_opens);
+        println!("{}", report);
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::io;
+
+use super::common::{format_arg, json_arg, output_option, quiet_arg, with_key_args};
+use super::json::{self, Json};
+use crate::error::Result;
+use crate::{crypto, header, stlio};
+
+fn with_write_args(cmd: Command) -> Command {
+    with_key_args(
+        cmd.arg(Arg::new("banner").long("banner").help(format!(
+            "Banner text, or one of the presets: {}",
+            header::preset_names().join(", ")
+        )))
+        .arg(Arg::new("hide").long("hide"))
+        .arg(format_arg())
+        .arg(
+            Arg::new("fingerprint")
+                .long("fingerprint")
+                .action(ArgAction::SetTrue)
+                .requires("key"),
+        ),
+    )
+}
+
+pub fn command() -> Command {
+    Command::new("StegaSTL header tool")
+        .about("Shows and rewrites the 80 byte header of a binary STL")
+        .subcommand(with_key_args(
+            Command::new("show")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(json_arg()),
+        ))
+        .subcommand(with_write_args(
+            Command::new("write")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(Arg::new("out_file_path").required(true)),
+        ))
+        .arg(quiet_arg())
+}
+
+pub fn stegastl_command() -> Command {
+    Command::new("header")
+        .about("Shows and rewrites the 80 byte header of a binary STL")
+        .subcommand_required(true)
+        .subcommand(with_key_args(
+            Command::new("show")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(json_arg()),
+        ))
+        .subcommand(with_write_args(
+            Command::new("write")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(output_option()),
+        ))
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("show", sub_m)) => show(sub_m),
+        Some(("write", sub_m)) => write(sub_m),
+        _ => Err(io::Error::other("Unknown command").into()),
+    }
+}
+
+pub fn show(args: &ArgMatches) -> Result<()> {
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+
+    let (stl, _mesh, stats) = stlio::load_indexed_stl(&in_file_path)?;
+    let bytes = stl.header.header;
+    let record = header::find_hidden(&bytes);
+    // null is no key or no fingerprint to check it against
+    let key_matches = match (record.as_ref().and_then(|r| r.fingerprint), &secret) {
+        (Some(fingerprint), Some(secret)) => Some(fingerprint == crypto::fingerprint(secret)?),
+        _ => None,
+    };
+    if args.get_flag("json") {
+        let hidden = record.map(|record| {
+            Json::object()
+                .with("payload_bytes", record.payload.len() as u64)
+                .with("text", String::from_utf8(record.payload.clone()).ok())
+                .with("payload_hex", hex_string(&record.payload))
+                .with("fingerprint", record.fingerprint.map(|f| hex_string(&f)))
+                .with("key_matches", key_matches)
+        });
+        let report = Json::document("header show")
+            .with("mesh", json::mesh_stats(&stats))
+            .with("banner", header::banner(&bytes))
+            .with("header_hex", hex_string(&bytes))
+            .with("hidden", hidden);
+        println!("{}", report);
+        return Ok(());
+    }
+
+    //with --quiet only the header itself is printed
+    say!(args, "{}", stats);
+    println!("Banner: {}", header::banner(&bytes));
+    for row in bytes.chunks(16) {
+        let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("    {}", hex.join(" "));
+    }
+    let Some(record) = record else {
+        println!("No hidden record");
+        return Ok(());
+    };
+    match String::from_utf8(record.payload.clone()) {
+        Ok(text) => println!("Hidden: {}", text),
+        Err(_) => println!("Hidden: {} bytes", record.payload.len()),
+    }
+    match (record.fingerprint, key_matches) {
+        (Some(_), Some(matches)) => println!("Key fingerprint matches: {}", matches),
+        (Some(fingerprint), None) => println!("Key fingerprint: {}", hex_string(&fingerprint)),
+        (None, _) => {}
+    }
+    Ok(())
+}
+
+pub fn write(args: &ArgMatches) -> Result<()> {
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let out_file_path: String = args.get_one::<String>("out_file_path").unwrap().clone();
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+
+    let format = stlio::StlFormat::from_name(args.get_one::<String>("format").unwrap()).unwrap();
+
+    let (mut stl, mesh, stats) = stlio::load_indexed_stl(&in_file_path)?;
+    say!(args, "{}", stats);
+    if let Some(banner) = args.get_one::<String>("banner") {
+        let text = header::preset_banner(banner).unwrap_or(banner);
+        stl.header.header = header::with_banner(text)?;
+    }
+
+    let fingerprint = match (&secret, args.get_flag("fingerprint")) {
+        (Some(secret), true) => Some(crypto::fingerprint(secret)?),
+        _ => None,
+    };
+    let hidden = args.get_one::<String>("hide");
+    if hidden.is_some() || fingerprint.is_some() {
+        let record = header::HiddenRecord {
+            fingerprint,
+            payload: hidden.map(|h| h.as_bytes().to_vec()).unwrap_or_default(),
+        };
+        header::hide(&mut stl.header.header, &record)?;
+    }
+
+    say!(args, "Banner: {}", header::banner(&stl.header.header));
+    say!(args, "Writing file {}", out_file_path);
+    stl.triangles = mesh.to_triangles();
+    stlio::write_stl(out_file_path, stl, format)?;
+    Ok(())
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
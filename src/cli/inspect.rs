@@ -0,0 +1,208 @@
+use clap::{Arg, ArgMatches, Command, value_parser};
+use std::collections::HashSet;
+
+use super::common::{json_arg, quiet_arg};
+use super::json::{self, Json};
+use crate::error::Result;
+use crate::manip::{PackedVertex, VertexSet};
+use crate::{attributes, digits, manip, normals, permute, rotate, stlio};
+
+pub fn command() -> Command {
+    Command::new("STL Inspect")
+        .about("Evaluates STL model files for viable bit-encoded capacity")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("max_bits")
+                .required(true)
+                .value_parser(value_parser!(u8).range(1..=64)),
+        )
+        .arg(json_arg())
+        .arg(quiet_arg())
+}
+
+pub fn stegastl_command() -> Command {
+    Command::new("inspect")
+        .about("Evaluates a mesh for viable bit-encoded capacity")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("max_bits")
+                .long("bits")
+                .short('b')
+                .help("Check every bit depth up to this one")
+                .required(true)
+                .value_parser(value_parser!(u8).range(1..=64)),
+        )
+        .arg(json_arg())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let file_path: String = matches.get_one::<String>("file").unwrap().clone();
+    let max_bits: u8 = *matches.get_one::<u8>("max_bits").unwrap();
+    inspect(file_path, max_bits, matches)
+}
+
+pub fn inspect(file_path: String, max_bits: u8, args: &ArgMatches) -> Result<()> {
+    //with --quiet only the tables are printed
+    let (_, indexed, source, stats) = stlio::load_indexed_mesh(&file_path)?;
+    say!(args, "{}", stats);
+    let uniq_vertices = source.vertices(&indexed);
+    manip::check_bit_depth(max_bits, uniq_vertices.component_bits())?;
+
+    let bit_checks = match &uniq_vertices {
+        VertexSet::Single(vertices) => check_bit_depths(max_bits, vertices),
+        VertexSet::Double(vertices) => check_bit_depths(max_bits, vertices),
+    };
+
+    //carriers that leave the geometry alone, on their own, only an STL has them
+    let (carriers, usage) = if source.is_stl() {
+        let triangles = indexed.to_triangles();
+        let carriers = vec![
+            ("normals", normals::capacity_bits(&triangles, 32)),
+            ("normals (8-bit)", normals::capacity_bits(&triangles, 8)),
+            (
+                "attributes",
+                attributes::capacity_bits(&triangles, attributes::AttributeMode::Raw),
+            ),
+            (
+                "attributes (color)",
+                attributes::capacity_bits(&triangles, attributes::AttributeMode::Color(2)),
+            ),
+            ("rotation", rotate::capacity_bits(&triangles, 0)),
+            ("permutation", permute::capacity_bits(&triangles, 0)),
+            (
+                "digits (2-digit)",
+                digits::capacity_bits(&triangles, digits::DigitMode::new(2, false), 0, 0),
+            ),
+        ];
+        (carriers, Some(attributes::usage(&triangles)))
+    } else {
+        (Vec::new(), None)
+    };
+
+    if args.get_flag("json") {
+        println!("{}", json_report(&stats, &bit_checks, &carriers, &usage));
+        return Ok(());
+    }
+
+    say!(args, "Encoding bits check:");
+    println!(
+        "{:9}{:>9}{:>15}{:>9}",
+        "Bits", "Safe", "Encodable Bits", "(Bytes)"
+    );
+    for (bits, safe, bits_available) in bit_checks.iter() {
+        println!(
+            "{:<9}{:>9}{:>15}{:>9}",
+            bits,
+            safe,
+            bits_available,
+            bits_available / 8
+        );
+    }
+
+    let Some(usage) = usage else {
+        return Ok(());
+    };
+    say!(args, "Triangle carriers:");
+    println!("{:18}{:>15}{:>9}", "Carrier", "Encodable Bits", "(Bytes)");
+    for (name, bits_available) in carriers.iter() {
+        println!(
+            "{:<18}{:>15}{:>9}",
+            name,
+            bits_available,
+            bits_available / 8
+        );
+    }
+    say!(
+        args,
+        "Attribute bytes in use: {} triangles, {} distinct values, {} flagged as color",
+        usage.in_use,
+        usage.distinct,
+        usage.color_flagged
+    );
+    Ok(())
+}
+
+fn json_report(
+    stats: &stlio::MeshStats,
+    bit_checks: &[(u8, bool, u64)],
+    carriers: &[(&str, u64)],
+    usage: &Option<attributes::AttributeUsage>,
+) -> Json {
+    let bit_depths = bit_checks
+        .iter()
+        .map(|(bits, safe, bits_available)| {
+            Json::object()
+                .with("bits", *bits)
+                .with("safe", *safe)
+                .with("encodable_bits", *bits_available)
+                .with("encodable_bytes", bits_available / 8)
+        })
+        .collect::<Vec<Json>>();
+    let carriers = carriers
+        .iter()
+        .map(|(name, bits_available)| {
+            Json::object()
+                .with("carrier", *name)
+                .with("encodable_bits", *bits_available)
+                .with("encodable_bytes", bits_available / 8)
+        })
+        .collect::<Vec<Json>>();
+    let attributes = usage.as_ref().map(|usage| {
+        Json::object()
+            .with("in_use", usage.in_use as u64)
+            .with("distinct", usage.distinct as u64)
+            .with("color_flagged", usage.color_flagged as u64)
+    });
+    Json::document("inspect")
+        .with("mesh", json::mesh_stats(stats))
+        .with("bit_depths", bit_depths)
+        .with("carriers", carriers)
+        .with("attributes", attributes)
+}
+
+fn check_bit_depths<V: PackedVertex>(max_bits: u8, uniq_vertices: &[V]) -> Vec<(u8, bool, u64)> {
+    (1..=max_bits)
+        .map(|i| {
+            let safe: bool = test_zeroed_bits(&i, uniq_vertices);
+            (i, safe, manip::get_available_bits(i, uniq_vertices))
+        })
+        .collect()
+}
+
+fn test_zeroed_bits<V: PackedVertex>(bits: &u8, source: &[V]) -> bool {
+    /*
+     * Reports back a validity check (true/false) based on a somewhat arbitrary and unscientific
+     * test condition:
+     *  - If the requested bits were zeroed off from all vertices on all triangles,
+     *  - would any originally unique vertices collapse to share a point?
+     *  - If so, invalid
+     *
+     *  Rationale:
+     *  - When data is encoded onto the bits, the model must physically change shape
+     *  - But if we can effectively discard n bits, and the model still seems to be made
+     *    of unique triangles that are still described by their own geometry,
+     *  - then it is likely (but not guaranteed) that the model can hold data without being
+     *    irredeemable damaged.
+     *
+     *  This will probably prove to be a poor analysis under intense or academic scrutiny,
+     *    but it works on my test cases close enough to be a poor-mans's heuristic.
+     */
+    let mut uniq_set = HashSet::<V>::new();
+
+    for v in source.iter() {
+        let masked = v.masked(*bits);
+        if !uniq_set.contains(&masked) {
+            uniq_set.insert(masked);
+        }
+    }
+
+    uniq_set.len() == source.len()
+}
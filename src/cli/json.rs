@@ -0,0 +1,166 @@
+use std::fmt;
+
+use crate::stlio::MeshStats;
+
+/*
+ *  Just enough JSON for --json. Every document is one object printed on its own line, starting
+ *  with the schema version and the command it reports on. Fields keep the order they're added
+ *  in. A new field can be added within a version, renaming or removing one needs a new version.
+ */
+pub const SCHEMA_VERSION: u64 = 1;
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(u64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    pub fn document(command: &str) -> Json {
+        Json::object()
+            .with("version", SCHEMA_VERSION)
+            .with("command", command)
+    }
+
+    pub fn object() -> Json {
+        Json::Object(Vec::new())
+    }
+
+    pub fn with(mut self, key: &'static str, value: impl Into<Json>) -> Json {
+        if let Json::Object(fields) = &mut self {
+            fields.push((key, value.into()));
+        }
+        self
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Json {
+        Json::Int(value)
+    }
+}
+
+impl From<u8> for Json {
+    fn from(value: u8) -> Json {
+        Json::Int(value as u64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Json {
+        Json::Float(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::Str(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::Str(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(value: Vec<Json>) -> Json {
+        Json::Array(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Int(value) => write!(f, "{}", value),
+            // f64 Display never uses an exponent, JSON has no NaN or infinity
+            Json::Float(value) if value.is_finite() => write!(f, "{}", value),
+            Json::Float(_) => write!(f, "null"),
+            Json::Str(text) => write_string(f, text),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+pub fn mesh_stats(stats: &MeshStats) -> Json {
+    Json::object()
+        .with("file", stats.file_path.as_str())
+        .with("triangles", stats.triangles)
+        .with("vertices", stats.vertices)
+        .with("double_precision", stats.double_precision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_output() {
+        let document = Json::document("decode")
+            .with("bits", Some(5u8))
+            .with("text", "say \"hi\"\n\u{1}")
+            .with("key_opens", None::<bool>)
+            .with("distance", 0.25)
+            .with("nan", f64::NAN)
+            .with("sizes", vec![Json::from(1u64), Json::object()]);
+        assert_eq!(
+            document.to_string(),
+            r#"{"version":1,"command":"decode","bits":5,"text":"say \"hi\"\n\u0001","key_opens":null,"distance":0.25,"nan":null,"sizes":[1,{}]}"#
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use clap::{ArgMatches, Command};
+use std::io;
+
+use crate::error::Result;
+
+/*
+ *  The command line of every tool. stegastl puts them all under one binary with the same
+ *  named options (--bits, --output, --format, the key and carrier options), and the single
+ *  purpose binaries (inspect, noise, text, data, header) keep their own argument styles on top
+ *  of the same handlers.
+ *
+ *      stegastl inspect <file> --bits <max>
+ *      stegastl noise <file> --output <prefix> --bits <max>
+ *      stegastl embed <in> <data> --output <out> [--bits <bits>]
+ *      stegastl extract <in> --output <out|-> [--bits <bits>]
+ *      stegastl verify <in> [--bits <bits>]
+ *      stegastl text embed <in> <text> --output <out> [--bits <bits>]
+ *      stegastl text extract <in> [--bits <bits>]
+ *      stegastl header show <in>
+ *      stegastl header write <in> --output <out>
+ */
+
+// progress and reports go to stdout unless --quiet or --json, errors always go to stderr;
+// every command takes --quiet, not all of them take --json
+macro_rules! say {
+    ($args:expr, $($arg:tt)*) => {
+        if !$args.get_flag("quiet") && !matches!($args.try_get_one::<bool>("json"), Ok(Some(true))) {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod common;
+pub mod data;
+pub mod header;
+pub mod inspect;
+mod json;
+pub mod noise;
+pub mod text;
+
+pub fn command() -> Command {
+    Command::new("stegastl")
+        .about("Embeds data in 3D meshes and gets it back out")
+        .subcommand_required(true)
+        .subcommand(inspect::stegastl_command())
+        .subcommand(noise::stegastl_command())
+        .subcommand(data::embed_command())
+        .subcommand(data::extract_command())
+        .subcommand(data::verify_command())
+        .subcommand(text::stegastl_command())
+        .subcommand(header::stegastl_command())
+        .arg(common::quiet_arg())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("inspect", sub_m)) => inspect::run(sub_m),
+        Some(("noise", sub_m)) => noise::run(sub_m),
+        Some(("embed", sub_m)) => data::encode(sub_m),
+        Some(("extract", sub_m)) => data::decode(sub_m),
+        Some(("verify", sub_m)) => data::verify(sub_m),
+        Some(("text", sub_m)) => text::run_stegastl(sub_m),
+        Some(("header", sub_m)) => header::run(sub_m),
+        _ => Err(io::Error::other("Unknown command").into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        command().debug_assert();
+        data::command().debug_assert();
+        text::command().debug_assert();
+        inspect::command().debug_assert();
+        noise::command().debug_assert();
+        header::command().debug_assert();
+
+        // both styles land on the same ids, so the handlers can't tell them apart
+        let tool = data::command()
+            .try_get_matches_from(["data", "encode", "in.stl", "out.stl", "data.bin", "5"])
+            .unwrap();
+        let unified = command()
+            .try_get_matches_from([
+                "stegastl", "embed", "in.stl", "data.bin", "-o", "out.stl", "--bits", "5",
+            ])
+            .unwrap();
+        let (_, tool) = tool.subcommand().unwrap();
+        let (_, unified) = unified.subcommand().unwrap();
+        for id in ["in_file_path", "out_file_path", "data_file_path"] {
+            assert_eq!(tool.get_one::<String>(id), unified.get_one::<String>(id));
+        }
+        assert_eq!(tool.get_one::<u8>("bits"), unified.get_one::<u8>("bits"));
+        assert!(!unified.get_flag("quiet"));
+        assert!(!unified.get_flag("json"));
+
+        // -q before or after the subcommand reaches every handler
+        for args in [
+            vec!["stegastl", "-q", "inspect", "in.stl", "-b", "4"],
+            vec![
+                "stegastl", "noise", "in.stl", "-o", "noisy", "-b", "4", "-q",
+            ],
+            vec!["stegastl", "-q", "header", "show", "in.stl"],
+            vec![
+                "stegastl", "header", "write", "in.stl", "-o", "out.stl", "-q",
+            ],
+            vec!["stegastl", "-q", "text", "extract", "in.stl"],
+        ] {
+            let matches = command().try_get_matches_from(&args).unwrap();
+            let mut sub_m = matches.subcommand().unwrap().1;
+            while let Some((_, next)) = sub_m.subcommand() {
+                sub_m = next;
+            }
+            assert!(sub_m.get_flag("quiet"), "{:?}", args);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use clap::{Arg, ArgMatches, Command, value_parser};
+
+use super::common::{format_arg, output_option, quiet_arg};
+use crate::carrier::Embedded;
+use crate::error::Result;
+use crate::manip::{self, PackedVertex, VertexSet};
+use crate::mesh::{self, IndexedMesh};
+use crate::stlio;
+
+pub fn command() -> Command {
+    Command::new("STL Stega Noise Eval Test Tool")
+        .about("Fuzzes STL file with random data in desired encode bit ranges, outputs sample files for inspection")
+        .arg(Arg::new("file").required(true).value_parser(value_parser!(String)))
+        .arg(Arg::new("prefix").required(true).value_parser(value_parser!(String)))
+        .arg(Arg::new("max_bits").required(true).value_parser(value_parser!(u8).range(1..=64)))
+        .arg(format_arg())
+        .arg(quiet_arg())
+}
+
+pub fn stegastl_command() -> Command {
+    Command::new("noise")
+        .about("Writes copies of a mesh with random data at every bit depth up to --bits")
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            output_option()
+                .id("prefix")
+                .help("Prefix of the sample files"),
+        )
+        .arg(
+            Arg::new("max_bits")
+                .long("bits")
+                .short('b')
+                .required(true)
+                .value_parser(value_parser!(u8).range(1..=64)),
+        )
+        .arg(format_arg())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    let file_path: String = matches.get_one::<String>("file").unwrap().clone();
+    let output_prefix: String = matches.get_one::<String>("prefix").unwrap().clone();
+    let max_bits: u8 = *matches.get_one::<u8>("max_bits").unwrap();
+    let format = stlio::StlFormat::from_name(matches.get_one::<String>("format").unwrap()).unwrap();
+    fuzz(file_path, output_prefix, max_bits, format, matches)
+}
+
+pub fn fuzz(
+    file_path: String,
+    output_prefix: String,
+    max_bits: u8,
+    format: stlio::StlFormat,
+    args: &ArgMatches,
+) -> Result<()> {
+    let (stl, indexed, mut source, stats) = stlio::load_indexed_mesh(&file_path)?;
+    say!(args, "{}", stats);
+    let uniq_vertices = source.vertices(&indexed);
+    manip::check_bit_depth(max_bits, uniq_vertices.component_bits())?;
+
+    for i in 1..=max_bits {
+        let fuzzed = match &uniq_vertices {
+            VertexSet::Single(vertices) => VertexSet::Single(fuzz_vertices(&i, vertices)),
+            VertexSet::Double(vertices) => VertexSet::Double(fuzz_vertices(&i, vertices)),
+        };
+        let filename: String = format!("{}_{}.{}", output_prefix, i, source.extension());
+        say!(args, "Writing file {} for {} encoded bits", filename, i);
+        write_file(filename, &stl, &indexed, fuzzed, format, &mut source)?;
+    }
+    Ok(())
+}
+
+fn write_file(
+    filename: String,
+    orig_stl: &stl::BinaryStlFile,
+    indexed: &IndexedMesh,
+    fuzzed: VertexSet,
+    format: stlio::StlFormat,
+    source: &mut stlio::MeshSource,
+) -> Result<()> {
+    let displacement = source.vertices(indexed).displacement(&fuzzed);
+    //a PLY takes its own positions back, everything else is rebuilt from the mesh
+    let (vertices, positions) = match (&source, fuzzed) {
+        (stlio::MeshSource::Ply(_), fuzzed) => (indexed.vertices.clone(), Some(fuzzed)),
+        (_, VertexSet::Single(fuzzed)) => (mesh::single_vertices(&fuzzed), None),
+        (_, fuzzed) => (indexed.vertices.clone(), Some(fuzzed)),
+    };
+    let triangle_order = mesh::file_order(indexed.triangles.len());
+    let embedded = Embedded {
+        stl: stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: orig_stl.header.header,
+                num_triangles: orig_stl.header.num_triangles,
+            },
+            triangles: indexed.write_triangles(&vertices, &triangle_order, &[]),
+        },
+        digits: None,
+        positions,
+        displacement,
+    };
+    stlio::write_mesh(filename, embedded, format, source)
+}
+
+fn fuzz_vertices<V: PackedVertex>(bits: &u8, source: &[V]) -> Vec<V> {
+    /*
+     *  Randomizes the content of the desired bits on every vertex.
+     *  Resulting STL can be used as a test sample for the chosen bit depth:
+     *   - to get a visual idea of how affected the functional model will be by encoded data
+     *   - to check how it will slice
+     */
+    source
+        .iter()
+        .map(|v| {
+            V::bit_shifts(*bits)
+                .iter()
+                .fold(*v, |f, shift| f.with_bit(*shift, rand::random_range(0..2)))
+        })
+        .collect()
+}
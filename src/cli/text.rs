@@ -0,0 +1,234 @@
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use std::io;
+
+use super::common::*;
+use super::json::{self, Json};
+use crate::embedder::Framing;
+use crate::error::Result;
+use crate::{crypto, stlio};
+
+/*
+ *  A short text in and out of a mesh, repeated as many times as asked. The decoded text is
+ *  printed, so with --quiet it can be piped, or read from the report with --json.
+ */
+fn with_encode_args(cmd: Command) -> Command {
+    with_carrier_args(with_scatter_args(with_key_args(
+        cmd.arg(
+            Arg::new("times")
+                .long("times")
+                .value_parser(value_parser!(u64))
+                .default_value("1"),
+        )
+        .arg(Arg::new("legacy").long("legacy").action(ArgAction::SetTrue))
+        .arg(
+            Arg::new("ecc")
+                .long("ecc")
+                .value_parser(value_parser!(u8).range(2..=128))
+                .conflicts_with("legacy"),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("legacy"),
+        )
+        .arg(format_arg())
+        .arg(json_arg()),
+    )))
+}
+
+fn with_decode_args(cmd: Command) -> Command {
+    with_carrier_args(with_scatter_args(with_key_args(
+        cmd.arg(Arg::new("legacy").long("legacy").action(ArgAction::SetTrue))
+            .arg(json_arg()),
+    )))
+}
+
+pub fn command() -> Command {
+    Command::new("StegaSTL test tool: Text Embedding")
+        .subcommand(with_encode_args(
+            Command::new("encode")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(Arg::new("out_file_path").required(true))
+                .arg(Arg::new("text").required(true))
+                .arg(bits_arg()),
+        ))
+        .subcommand(with_decode_args(
+            Command::new("decode")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(bits_arg()),
+        ))
+        .arg(quiet_arg())
+}
+
+pub fn run(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("encode", sub_m)) => encode(sub_m),
+        Some(("decode", sub_m)) => decode(sub_m),
+        _ => Err(io::Error::other("Unknown command").into()),
+    }
+}
+
+pub fn stegastl_command() -> Command {
+    Command::new("text")
+        .about("Embeds and extracts a short text")
+        .subcommand_required(true)
+        .subcommand(with_encode_args(
+            Command::new("embed")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(Arg::new("text").required(true))
+                .arg(output_option())
+                .arg(bits_option()),
+        ))
+        .subcommand(with_decode_args(
+            Command::new("extract")
+                .arg(Arg::new("in_file_path").required(true))
+                .arg(bits_option()),
+        ))
+}
+
+pub fn run_stegastl(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        Some(("embed", sub_m)) => encode(sub_m),
+        Some(("extract", sub_m)) => decode(sub_m),
+        _ => Err(io::Error::other("Unknown command").into()),
+    }
+}
+
+pub fn encode(args: &ArgMatches) -> Result<()> {
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let out_file_path: String = args.get_one::<String>("out_file_path").unwrap().clone();
+    let text: String = args.get_one::<String>("text").unwrap().clone();
+    let bits: Option<u8> = vertex_bits(args)?;
+    let times: u64 = *args.get_one::<u64>("times").unwrap();
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+    let framing = framing(args);
+
+    let (stl, indexed, mut mesh, stats) = load_mesh(in_file_path, args)?;
+    check_source(&mesh, args)?;
+    check_attributes(&indexed, args)?;
+    let format = output_format(args)?;
+    let seed = scatter_seed(&secret, args)?;
+    let writer = carrier_writer(&indexed, &mesh, bits, seed, args)?;
+    let available_bytes: u64 = writer.capacity_bits() / 8;
+
+    let embedder = embedder(&stl, &secret, framing, args);
+    let sealed = embedder.seal_repeated(text.as_bytes(), times, available_bytes)?;
+    if let Some(compressed_bytes) = sealed.compressed_bytes {
+        say!(
+            args,
+            "Text compressed from {} to {} bytes",
+            sealed.input_bytes,
+            compressed_bytes
+        );
+    }
+    if secret.is_some() {
+        say!(args, "Text encrypted");
+    }
+    let label = carrier_label(bits, args);
+    say!(
+        args,
+        "{} encoding `{}` {} times for {} bytes of text incl header",
+        label,
+        text,
+        times,
+        sealed.stored_bytes
+    );
+    say!(
+        args,
+        "{} storage provides {} bytes of stored data incl header",
+        label,
+        available_bytes
+    );
+    let (text_bytes, stored_bytes) = (sealed.input_bytes, sealed.stored_bytes);
+    let embedded = embedder.write(sealed, writer, &indexed)?;
+
+    let displacement = embedded.displacement;
+    say!(args, "Writing file {}", out_file_path);
+    stlio::write_mesh(out_file_path.to_string(), embedded, format, &mut mesh)?;
+    if args.get_flag("json") {
+        let report = Json::document("text encode")
+            .with("mesh", json::mesh_stats(&stats))
+            .with("output", out_file_path)
+            .with("bits", bits)
+            .with("framing", framing.name())
+            .with("payload_bytes", text_bytes)
+            .with("bytes_written", stored_bytes)
+            .with("capacity_bytes", available_bytes)
+            .with("compressed", args.get_flag("compress"))
+            .with("encrypted", secret.is_some())
+            .with("vertices_touched", displacement.vertices_touched)
+            .with("max_displacement", displacement.max_distance);
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+fn framing(args: &ArgMatches) -> Framing {
+    if args.get_flag("legacy") {
+        Framing::Legacy
+    } else {
+        Framing::Frame
+    }
+}
+
+pub fn decode(args: &ArgMatches) -> Result<()> {
+    let in_file_path: String = args.get_one::<String>("in_file_path").unwrap().clone();
+    let secret = crypto::load_secret(
+        args.get_one::<String>("passphrase"),
+        args.get_one::<String>("key_file"),
+    )?;
+    let framing = framing(args);
+
+    let text = carrier_text(&in_file_path, args)?;
+    let (stl, indexed, mesh, stats) = load_mesh(in_file_path, args)?;
+    check_source(&mesh, args)?;
+    let seed = scatter_seed(&secret, args)?;
+    let bits = read_bits(
+        &indexed,
+        &mesh,
+        &text,
+        seed,
+        framing == Framing::Frame,
+        args,
+    )?;
+
+    let extractor = extractor(&stl, &secret, framing);
+    let reader = carrier_reader(&indexed, &mesh, &text, bits, seed, args)?;
+    let stored = extractor.read(reader)?;
+    say_stored(&stored, args);
+    //only a frame has a checksum and flags, legacy text is taken as given
+    let checksum = if stored.header.is_some() {
+        "ok"
+    } else {
+        "none"
+    };
+    let (corrected, encrypted, compressed) =
+        (stored.corrected, stored.encrypted, stored.compressed);
+    let output = extractor.open(stored)?;
+
+    let payload_bytes = output.len() as u64;
+    let output = String::from_utf8(output)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Decoded text is not UTF-8"))?;
+    if args.get_flag("json") {
+        let report = Json::document("text decode")
+            .with("mesh", json::mesh_stats(&stats))
+            .with("bits", bits)
+            .with("framing", framing.name())
+            .with("payload_bytes", payload_bytes)
+            .with("checksum", checksum)
+            .with("corrected_bytes", corrected as u64)
+            .with("encrypted", encrypted)
+            .with("compressed", compressed)
+            .with("text", output);
+        println!("{}", report);
+        return Ok(());
+    }
+    say!(args, "======== BEGIN ENCODED MESSAGE ========");
+    println!("{}", output);
+    say!(args, "======== END ENCODED MESSAGE ========");
+    Ok(())
+}
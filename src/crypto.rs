@@ -0,0 +1,213 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt;
+use std::fs;
+
+pub const SALT_BYTES: usize = 16;
+pub const NONCE_BYTES: usize = 12;
+pub const TAG_BYTES: usize = 16;
+// salt and nonce are stored ahead of the ciphertext, the tag is appended to it
+pub const SEAL_OVERHEAD: u64 = (SALT_BYTES + NONCE_BYTES + TAG_BYTES) as u64;
+// salt, nonce and the encrypted 64-bit length of a headerless sealed payload
+pub const LENGTH_PREFIX_BYTES: usize = SALT_BYTES + NONCE_BYTES + 8 + TAG_BYTES;
+pub const SEAL_WITH_LENGTH_OVERHEAD: u64 = (LENGTH_PREFIX_BYTES + TAG_BYTES) as u64;
+
+#[derive(Debug, PartialEq)]
+pub enum CryptoError {
+    KeyDerivation,
+    Truncated,
+    Authentication,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::KeyDerivation => write!(f, "unable to derive key from secret"),
+            CryptoError::Truncated => write!(f, "encrypted payload is truncated"),
+            CryptoError::Authentication => {
+                write!(f, "decryption failed: wrong key or corrupted payload")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+pub fn derive_key(secret: &[u8], salt: &[u8]) -> Result<Key, CryptoError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(secret, salt, &mut key)
+        .map_err(|_| CryptoError::KeyDerivation)?;
+    Ok(key)
+}
+
+pub fn derive_seed(secret: &[u8]) -> Result<[u8; 32], CryptoError> {
+    // fixed salt on purpose: the seed has to be reproducible from the secret alone
+    let key = derive_key(secret, b"stegastl scatter seed")?;
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&key);
+    Ok(seed)
+}
+
+pub fn fingerprint(secret: &[u8]) -> Result<[u8; 8], CryptoError> {
+    // short, stable tag for a secret that says nothing about the secret itself
+    let key = derive_key(secret, b"stegastl key fingerprint")?;
+    let mut fingerprint = [0u8; 8];
+    fingerprint.copy_from_slice(&key[..8]);
+    Ok(fingerprint)
+}
+
+pub fn seal(secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    /*
+     *  Layout of a sealed payload:
+     *   - 16 bytes random salt for the passphrase KDF (Argon2id)
+     *   - 12 bytes random nonce
+     *   - ChaCha20-Poly1305 ciphertext, with its 16 byte tag on the end
+     */
+    let salt: [u8; SALT_BYTES] = rand::random();
+    let nonce: [u8; NONCE_BYTES] = rand::random();
+    let cipher = ChaCha20Poly1305::new(&derive_key(secret, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| CryptoError::Authentication)?;
+
+    let mut sealed = Vec::<u8>::with_capacity(SALT_BYTES + NONCE_BYTES + ciphertext.len());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+pub fn open(secret: &[u8], sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    if (sealed.len() as u64) < SEAL_OVERHEAD {
+        return Err(CryptoError::Truncated);
+    }
+    let (salt, rest) = sealed.split_at(SALT_BYTES);
+    let (nonce, ciphertext) = rest.split_at(NONCE_BYTES);
+    let cipher = ChaCha20Poly1305::new(&derive_key(secret, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CryptoError::Authentication)
+}
+
+fn body_nonce(nonce: &[u8]) -> [u8; NONCE_BYTES] {
+    // the length and the body are sealed under the same key, so they must not share a nonce
+    let mut body_nonce = [0u8; NONCE_BYTES];
+    body_nonce.copy_from_slice(nonce);
+    body_nonce[NONCE_BYTES - 1] ^= 1;
+    body_nonce
+}
+
+pub fn seal_with_length(secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    /*
+     *  Layout of a sealed payload carrying its own length, for headerless embedding:
+     *   - 16 bytes random salt, 12 bytes random nonce
+     *   - the plaintext length as a sealed little-endian u64 (8 bytes + 16 byte tag)
+     *   - the sealed plaintext, under the nonce with its last bit flipped
+     *  Nothing in it can be told apart from random bytes without the secret.
+     */
+    let salt: [u8; SALT_BYTES] = rand::random();
+    let nonce: [u8; NONCE_BYTES] = rand::random();
+    let cipher = ChaCha20Poly1305::new(&derive_key(secret, &salt)?);
+    let length = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            &(plaintext.len() as u64).to_le_bytes()[..],
+        )
+        .map_err(|_| CryptoError::Authentication)?;
+    let body = cipher
+        .encrypt(Nonce::from_slice(&body_nonce(&nonce)), plaintext)
+        .map_err(|_| CryptoError::Authentication)?;
+
+    let mut sealed = Vec::<u8>::with_capacity(LENGTH_PREFIX_BYTES + body.len());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&length);
+    sealed.extend_from_slice(&body);
+    Ok(sealed)
+}
+
+pub struct LengthPrefix {
+    cipher: ChaCha20Poly1305,
+    nonce: [u8; NONCE_BYTES],
+    body_bytes: u64,
+}
+
+impl LengthPrefix {
+    pub fn body_bytes(&self) -> u64 {
+        self.body_bytes
+    }
+
+    pub fn open_body(&self, body: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if (body.len() as u64) < self.body_bytes {
+            return Err(CryptoError::Truncated);
+        }
+        self.cipher
+            .decrypt(Nonce::from_slice(&self.nonce), body)
+            .map_err(|_| CryptoError::Authentication)
+    }
+}
+
+pub fn open_length(secret: &[u8], prefix: &[u8]) -> Result<LengthPrefix, CryptoError> {
+    if prefix.len() < LENGTH_PREFIX_BYTES {
+        return Err(CryptoError::Truncated);
+    }
+    let (salt, rest) = prefix.split_at(SALT_BYTES);
+    let (nonce, rest) = rest.split_at(NONCE_BYTES);
+    let cipher = ChaCha20Poly1305::new(&derive_key(secret, salt)?);
+    let length = cipher
+        .decrypt(Nonce::from_slice(nonce), &rest[..8 + TAG_BYTES])
+        .map_err(|_| CryptoError::Authentication)?;
+    let mut length_bytes = [0u8; 8];
+    length_bytes.copy_from_slice(&length);
+
+    Ok(LengthPrefix {
+        cipher,
+        nonce: body_nonce(nonce),
+        body_bytes: u64::from_le_bytes(length_bytes) + TAG_BYTES as u64,
+    })
+}
+
+pub fn load_secret(
+    passphrase: Option<&String>,
+    key_file: Option<&String>,
+) -> std::io::Result<Option<Vec<u8>>> {
+    // a key file is used as raw secret material, same as a (very long) passphrase
+    match (passphrase, key_file) {
+        (Some(p), _) => Ok(Some(p.as_bytes().to_vec())),
+        (None, Some(path)) => Ok(Some(fs::read(path)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let sealed = seal(b"hunter2", b"a benchy full of hamlet").unwrap();
+        assert_eq!(sealed.len() as u64, SEAL_OVERHEAD + 23);
+        let opened = open(b"hunter2", &sealed).unwrap();
+        assert_eq!(opened, b"a benchy full of hamlet");
+    }
+
+    #[test]
+    fn test_open_wrong_key() {
+        let sealed = seal(b"hunter2", b"puffin").unwrap();
+        assert_eq!(open(b"hunter3", &sealed), Err(CryptoError::Authentication));
+        assert_eq!(open(b"hunter2", &sealed[..20]), Err(CryptoError::Truncated));
+    }
+
+    #[test]
+    fn test_seal_with_length() {
+        let sealed = seal_with_length(b"hunter2", b"puffin").unwrap();
+        assert_eq!(sealed.len() as u64, SEAL_WITH_LENGTH_OVERHEAD + 6);
+        let (prefix, body) = sealed.split_at(LENGTH_PREFIX_BYTES);
+        let length = open_length(b"hunter2", prefix).unwrap();
+        assert_eq!(length.body_bytes(), body.len() as u64);
+        assert_eq!(length.open_body(body).unwrap(), b"puffin");
+        assert!(open_length(b"hunter3", prefix).is_err());
+    }
+}
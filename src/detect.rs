@@ -0,0 +1,147 @@
+use crate::carrier::CarrierReader;
+use crate::error::{Result, StegaError};
+use crate::reader_writer::{self, BinaryDecoder, FrameHeader};
+
+pub fn frame_at_depth<F>(bits: u8, make_reader: &F) -> Result<Option<FrameHeader>>
+where
+    F: Fn(u8) -> Result<CarrierReader>,
+{
+    /*
+     *  Reads a candidate frame at the given bit depth and only accepts it if
+     *   - the magic and version match
+     *   - the claimed payload fits in what the mesh can hold at this depth
+     *   - the CRC32 trailer matches the header and payload, after any error correction
+     *  No magic is no frame at this depth. Once the magic matches, the frame is ours and any
+     *  other failure is returned as the error it is.
+     */
+    let mut reader = make_reader(bits)?;
+    let available_bytes = reader.capacity_bits() / 8;
+    if available_bytes < reader_writer::FRAME_OVERHEAD {
+        return Ok(None);
+    }
+    let mut decoder = BinaryDecoder::new();
+
+    let mut magic = None;
+    let read = |count| {
+        let bytes = reader.read_bytes(&mut decoder, count);
+        magic.get_or_insert_with(|| bytes.starts_with(&reader_writer::FRAME_MAGIC));
+        bytes
+    };
+    match reader_writer::read_frame(read, available_bytes) {
+        Ok(frame) => Ok(Some(frame.header)),
+        Err(e) if magic == Some(true) => Err(e.into()),
+        Err(_) => Ok(None),
+    }
+}
+
+pub fn detect_bit_depth<F>(max_bits: u8, make_reader: F) -> Result<Option<u8>>
+where
+    F: Fn(u8) -> Result<CarrierReader>,
+{
+    // make_reader lets callers apply the same carriers and keyed scattering they would decode with
+    // max_bits is the width of one coordinate, 32 for f32 and 64 for f64 positions
+    // a reader that can't be made at all is an error, not a depth without a payload
+    // a damaged frame is reported if no other depth has a good one
+    let mut damaged = None;
+    for bits in 1..=max_bits {
+        match frame_at_depth(bits, &make_reader) {
+            Ok(Some(_)) => return Ok(Some(bits)),
+            Ok(None) => {}
+            Err(StegaError::Frame(e)) => {
+                damaged.get_or_insert(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    match damaged {
+        Some(e) => Err(e.into()),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::carrier::Carriers;
+    use crate::manip::{ManipulatorMode, VertexManipulator};
+    use crate::mesh::IndexedMesh;
+    use crate::reader_writer::{BinaryEncoder, BitFeed, FrameError, FrameHeader, FramedEncoder};
+    use std::io::Cursor;
+
+    fn embed(
+        stl: &stl::BinaryStlFile,
+        carriers: Carriers,
+        encoder: &mut impl BitFeed,
+    ) -> stl::BinaryStlFile {
+        let indexed = IndexedMesh::from_triangles(&stl.triangles);
+        let mut writer = carriers
+            .writer(&indexed, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+            })
+            .unwrap();
+        writer.write_feed(encoder).unwrap();
+        writer.finish(&stl.header, &indexed).stl
+    }
+
+    #[test]
+    fn test_detect_bit_depth() {
+        let f = |n: u32| (n as f32) * 1.5 + 0.25;
+        let triangles: Vec<stl::Triangle> = (0..70)
+            .map(|i| stl::Triangle {
+                normal: [0.0, 0.0, 1.0],
+                v1: [f(3 * i), f(i), f(i + 2)],
+                v2: [f(3 * i + 1), f(i), f(i + 2)],
+                v3: [f(3 * i + 2), f(i), f(i + 2)],
+                attr_byte_count: 0,
+            })
+            .collect();
+        let stl = stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: [0u8; 80],
+                num_triangles: triangles.len() as u32,
+            },
+            triangles,
+        };
+        let carriers = |bits| Carriers {
+            vertex_bits: Some(bits),
+            ..Default::default()
+        };
+
+        let encoded = embed(
+            &stl,
+            carriers(6),
+            &mut FramedEncoder::new(Cursor::new(b"detect me".to_vec()), 9, 0),
+        );
+
+        let reader = |triangles: &[stl::Triangle], bits| {
+            let indexed = IndexedMesh::from_triangles(triangles);
+            carriers(bits).reader(&indexed, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::READ, v, bits)
+            })
+        };
+        assert_eq!(
+            detect_bit_depth(32, |bits| reader(&encoded.triangles, bits)).unwrap(),
+            Some(6)
+        );
+        assert_eq!(
+            detect_bit_depth(32, |bits| reader(&stl.triangles, bits)).unwrap(),
+            None
+        );
+        assert!(detect_bit_depth(32, |bits| reader(&[], bits)).is_err());
+
+        // a frame with the right magic and a bad checksum is reported, not skipped
+        let mut frame = FrameHeader::new(0, 9).to_bytes();
+        frame.extend(b"detect me");
+        frame.extend([0u8; 4]);
+        let length = frame.len() as u64;
+        let damaged = embed(
+            &stl,
+            carriers(6),
+            &mut BinaryEncoder::headerless(Cursor::new(frame), length),
+        );
+        assert!(matches!(
+            detect_bit_depth(32, |bits| reader(&damaged.triangles, bits)),
+            Err(StegaError::Frame(FrameError::ChecksumMismatch { .. }))
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use std::io;
+
+/*
+ *  An ASCII STL stores numbers as text, so the float bits are only half the story: the same
+ *  f32 can be printed many ways. Nine significant digits pin down any f32 exactly, and digits
+ *  printed past those are too small to move the value when it is read back, so they are free.
+ *
+ *  Every coordinate that carries data is printed with nine real significant digits followed
+ *  by `digits` data digits, e.g. 1.23456789|042e1. Those data digits hold a number below
+ *  10^digits, which carries its value modulo the largest power of two that fits, with the
+ *  leftover room filled at random so the digits look evenly spread.
+ *
+ *  Optionally the exponent carries one more bit: Rust style `e5`/`e-5`, or C printf style
+ *  `e+05`/`e-05`. Exponents of -10 and below look the same both ways, so values under 1e-9
+ *  carry nothing there.
+ *
+ *  Zero can't take any digits without becoming non-zero. A coordinate only carries data when
+ *  it's non-zero with the bits the other carriers own masked off, so the reader can tell which
+ *  ones do from the values alone, before or after those carriers have written their bits.
+ *
+ *  Coordinates are walked in file order: the facet normal x, y, z, then the three vertices.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DigitMode {
+    pub digits: u8,
+    pub exponent: bool,
+}
+
+const REAL_DIGITS: usize = 9;
+const COORDINATES_PER_FACET: usize = 12;
+
+impl DigitMode {
+    pub fn new(digits: u8, exponent: bool) -> DigitMode {
+        assert!(digits > 0 && digits <= 9);
+        DigitMode { digits, exponent }
+    }
+
+    fn digit_bits(&self) -> u32 {
+        10u64.pow(self.digits as u32).ilog2()
+    }
+}
+
+fn keep_mask(carried_bits: u8) -> u32 {
+    // sign aside, the bits left alone by a carrier that owns the low carried_bits
+    let keep: u32 = match carried_bits {
+        32.. => 0,
+        bits => !0 << bits,
+    };
+    keep & 0x7fff_ffff
+}
+
+struct Eligibility {
+    normal_keep: u32,
+    vertex_keep: u32,
+}
+
+impl Eligibility {
+    fn new(vertex_bits: u8, normal_bits: u8) -> Eligibility {
+        Eligibility {
+            normal_keep: keep_mask(normal_bits),
+            vertex_keep: keep_mask(vertex_bits),
+        }
+    }
+
+    fn masked(&self, position: usize, value: f32) -> f32 {
+        let keep = match position % COORDINATES_PER_FACET {
+            0..3 => self.normal_keep,
+            _ => self.vertex_keep,
+        };
+        f32::from_bits(value.to_bits() & keep)
+    }
+
+    fn carries(&self, position: usize, value: f32) -> bool {
+        self.masked(position, value) != 0.0
+    }
+
+    fn exponent_carries(&self, position: usize, value: f32) -> bool {
+        // the masked value is never larger, so the printed exponent is -9 or above as well
+        self.masked(position, value) >= 1e-9
+    }
+}
+
+fn facet_values(tri: &stl::Triangle) -> impl Iterator<Item = f32> + '_ {
+    [&tri.normal, &tri.v1, &tri.v2, &tri.v3]
+        .into_iter()
+        .flat_map(|v| v.iter().copied())
+}
+
+pub fn capacity_bits(
+    triangles: &[stl::Triangle],
+    mode: DigitMode,
+    vertex_bits: u8,
+    normal_bits: u8,
+) -> u64 {
+    let eligibility = Eligibility::new(vertex_bits, normal_bits);
+    let mut capacity: u64 = 0;
+    for (position, value) in triangles.iter().flat_map(facet_values).enumerate() {
+        if !eligibility.carries(position, value) {
+            continue;
+        }
+        capacity += mode.digit_bits() as u64;
+        if mode.exponent && eligibility.exponent_carries(position, value) {
+            capacity += 1;
+        }
+    }
+    capacity
+}
+
+pub struct DigitWriter {
+    mode: DigitMode,
+    eligibility: Eligibility,
+    capacity: u64,
+    bits: Vec<u8>,
+    read_position: usize,
+    coordinate: usize,
+}
+
+impl DigitWriter {
+    pub fn new(
+        triangles: &[stl::Triangle],
+        mode: DigitMode,
+        vertex_bits: u8,
+        normal_bits: u8,
+    ) -> DigitWriter {
+        DigitWriter {
+            mode,
+            eligibility: Eligibility::new(vertex_bits, normal_bits),
+            capacity: capacity_bits(triangles, mode, vertex_bits, normal_bits),
+            bits: Vec::<u8>::new(),
+            read_position: 0,
+            coordinate: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn pushed_bits(&self) -> u64 {
+        self.bits.len() as u64
+    }
+
+    pub fn push_bit(&mut self, bit: u8) {
+        if self.pushed_bits() < self.capacity {
+            self.bits.push(bit);
+        }
+    }
+
+    fn take_bits(&mut self, count: u32) -> Option<u64> {
+        // past the end of the payload the digits are left random
+        if self.read_position >= self.bits.len() {
+            return None;
+        }
+        let mut value: u64 = 0;
+        for _ in 0..count {
+            let bit = self.bits.get(self.read_position).copied().unwrap_or(0);
+            value = value << 1 | bit as u64;
+            self.read_position += 1;
+        }
+        Some(value)
+    }
+
+    pub fn format(&mut self, value: f32) -> io::Result<String> {
+        /*
+         *  Prints the next coordinate in file order. Every value is printed with the same
+         *  number of significant digits, carrying or not, so the ones that don't carry look
+         *  like the ones that do.
+         */
+        if !value.is_finite() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} can't be written exactly in an ASCII STL", value),
+            ));
+        }
+        let carries = self.eligibility.carries(self.coordinate, value);
+        let exponent_carries = self.eligibility.exponent_carries(self.coordinate, value);
+        self.coordinate += 1;
+
+        let digits = self.mode.digits as usize;
+        let (mantissa, exponent) = if carries {
+            let printed = format!("{:.8e}", value);
+            let (real, exponent) = printed.split_once('e').unwrap();
+            let limit = 10u64.pow(self.mode.digits as u32);
+            let step = 1u64 << self.mode.digit_bits();
+            let data = match self.take_bits(self.mode.digit_bits()) {
+                Some(bits) => bits + step * rand::random_range(0..(limit - bits).div_ceil(step)),
+                None => rand::random_range(0..limit),
+            };
+            (
+                format!("{}{:0width$}", real, data, width = digits),
+                exponent.parse::<i32>().unwrap(),
+            )
+        } else {
+            let printed = format!("{:.*e}", REAL_DIGITS - 1 + digits, value);
+            let (mantissa, exponent) = printed.split_once('e').unwrap();
+            (mantissa.to_string(), exponent.parse::<i32>().unwrap())
+        };
+
+        let c_style = match self.mode.exponent {
+            false => false,
+            true if exponent_carries => {
+                self.take_bits(1)
+                    .unwrap_or_else(|| rand::random_range(0..2))
+                    == 1
+            }
+            true => rand::random(),
+        };
+        Ok(match c_style {
+            true => format!(
+                "{}e{}{:02}",
+                mantissa,
+                if exponent < 0 { '-' } else { '+' },
+                exponent.abs()
+            ),
+            false => format!("{}e{}", mantissa, exponent),
+        })
+    }
+}
+
+pub struct DigitReader {
+    bits: Vec<u8>,
+    position: usize,
+}
+
+impl DigitReader {
+    pub fn new(
+        text: &[u8],
+        mode: DigitMode,
+        vertex_bits: u8,
+        normal_bits: u8,
+    ) -> io::Result<DigitReader> {
+        let text = std::str::from_utf8(text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let eligibility = Eligibility::new(vertex_bits, normal_bits);
+        let digit_bits = mode.digit_bits();
+        let step = 1u64 << digit_bits;
+        let mut bits = Vec::<u8>::new();
+
+        let mut coordinate: usize = 0;
+        let mut tokens = text.split_ascii_whitespace();
+        while let Some(token) = tokens.next() {
+            if token != "normal" && token != "vertex" {
+                continue;
+            }
+            for printed in tokens.by_ref().take(3) {
+                let value = printed.parse::<f32>().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("`{}` is not a number", printed),
+                    )
+                })?;
+                let carries = eligibility.carries(coordinate, value);
+                let exponent_carries = eligibility.exponent_carries(coordinate, value);
+                coordinate += 1;
+                if !carries {
+                    continue;
+                }
+
+                let (mantissa, exponent) = printed.split_once(['e', 'E']).unwrap_or((printed, ""));
+                let mantissa_digits: Vec<u64> = mantissa
+                    .chars()
+                    .filter_map(|c| c.to_digit(10))
+                    .map(|d| d as u64)
+                    .collect();
+                let data_digits =
+                    &mantissa_digits[mantissa_digits.len().saturating_sub(mode.digits as usize)..];
+                let data = data_digits.iter().fold(0, |n, d| n * 10 + d) % step;
+                for shift in (0..digit_bits).rev() {
+                    bits.push((data >> shift & 1) as u8);
+                }
+
+                if mode.exponent && exponent_carries {
+                    // plain `e0` has a leading zero too, only a sign or `-0` tells C style apart
+                    let c_style = exponent.starts_with('+') || exponent.starts_with("-0");
+                    bits.push(c_style as u8);
+                }
+            }
+        }
+        Ok(DigitReader { bits, position: 0 })
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.bits.len() as u64
+    }
+
+    pub fn read_bits(&self) -> u64 {
+        self.position as u64
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        let bit = self.bits.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stlio;
+
+    #[test]
+    fn test_digit_roundtrip() {
+        let triangles: Vec<stl::Triangle> = (0..40)
+            .map(|i| stl::Triangle {
+                normal: [0.0, -0.6, 0.8],
+                v1: [i as f32 * 0.37 - 5.0, 1e-12, f32::from_bits(0x3f80_0001)],
+                v2: [-3.5e12, i as f32 + 1.0, 0.1],
+                v3: [1e20, 123.456, 7.0e-3],
+                attr_byte_count: 0,
+            })
+            .collect();
+        let stl = stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: [b' '; 80],
+                num_triangles: triangles.len() as u32,
+            },
+            triangles,
+        };
+        let mode = DigitMode::new(3, true);
+        let mut writer = DigitWriter::new(&stl.triangles, mode, 0, 0);
+        // the zero normal component and the 1e-12 exponent carry less than the rest
+        assert_eq!(writer.capacity_bits(), 40 * (11 * 9 + 10));
+
+        let payload: Vec<u8> = (0..1000).map(|i| ((i * 5) % 7 < 3) as u8).collect();
+        for bit in payload.iter() {
+            writer.push_bit(*bit);
+        }
+        let mut text = Vec::<u8>::new();
+        stlio::write_ascii_stl_with(&mut text, &stl, |v| writer.format(v)).unwrap();
+
+        // the values themselves come back exactly
+        let read = stlio::read_stl(&text).unwrap();
+        for (a, b) in read.triangles.iter().zip(stl.triangles.iter()) {
+            for (va, vb) in [
+                (a.normal, b.normal),
+                (a.v1, b.v1),
+                (a.v2, b.v2),
+                (a.v3, b.v3),
+            ] {
+                assert_eq!(va.map(f32::to_bits), vb.map(f32::to_bits));
+            }
+        }
+
+        let mut reader = DigitReader::new(&text, mode, 0, 0).unwrap();
+        assert_eq!(reader.capacity_bits(), 40 * (11 * 9 + 10));
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+}
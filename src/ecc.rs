@@ -0,0 +1,344 @@
+use std::fmt;
+
+/*
+ *  Reed-Solomon over GF(2^8), primitive polynomial 0x11d, generator 2, first consecutive root 0.
+ *  Data is cut into blocks of (255 - parity) bytes, the last one shortened, and each block gets
+ *  `parity` check bytes, correcting up to parity / 2 damaged bytes per block.
+ *  The encoded blocks are then interleaved byte by byte, so a run of damaged vertices is spread
+ *  across every block instead of exhausting the correction budget of one.
+ */
+const FIELD_SIZE: usize = 255;
+const PRIMITIVE_POLY: u16 = 0x11d;
+
+pub const MIN_PARITY: u8 = 2;
+pub const MAX_PARITY: u8 = 128;
+
+#[derive(Debug, PartialEq)]
+pub enum EccError {
+    Uncorrectable { block: usize },
+}
+
+impl fmt::Display for EccError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EccError::Uncorrectable { block } => {
+                write!(f, "too many errors to correct in ECC block {}", block)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EccError {}
+
+struct Gf {
+    exp: [u8; FIELD_SIZE * 2],
+    log: [u8; FIELD_SIZE + 1],
+}
+
+impl Gf {
+    fn new() -> Gf {
+        let mut exp = [0u8; FIELD_SIZE * 2];
+        let mut log = [0u8; FIELD_SIZE + 1];
+        let mut x: u16 = 1;
+        for (i, e) in exp.iter_mut().take(FIELD_SIZE).enumerate() {
+            *e = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= PRIMITIVE_POLY;
+            }
+        }
+        exp.copy_within(0..FIELD_SIZE, FIELD_SIZE);
+        Gf { exp, log }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {
+            return 0;
+        }
+        let l = self.log[a as usize] as usize + FIELD_SIZE - self.log[b as usize] as usize;
+        self.exp[l % FIELD_SIZE]
+    }
+
+    fn pow(&self, a: u8, power: i32) -> u8 {
+        let l = (self.log[a as usize] as i32 * power).rem_euclid(FIELD_SIZE as i32);
+        self.exp[l as usize]
+    }
+
+    fn inverse(&self, a: u8) -> u8 {
+        self.exp[FIELD_SIZE - self.log[a as usize] as usize]
+    }
+
+    // polynomials are stored highest degree first
+    fn poly_scale(&self, p: &[u8], x: u8) -> Vec<u8> {
+        p.iter().map(|c| self.mul(*c, x)).collect()
+    }
+
+    fn poly_add(&self, p: &[u8], q: &[u8]) -> Vec<u8> {
+        let len = p.len().max(q.len());
+        let mut r = vec![0u8; len];
+        for (i, c) in p.iter().enumerate() {
+            r[i + len - p.len()] = *c;
+        }
+        for (i, c) in q.iter().enumerate() {
+            r[i + len - q.len()] ^= *c;
+        }
+        r
+    }
+
+    fn poly_mul(&self, p: &[u8], q: &[u8]) -> Vec<u8> {
+        let mut r = vec![0u8; p.len() + q.len() - 1];
+        for (j, qc) in q.iter().enumerate() {
+            for (i, pc) in p.iter().enumerate() {
+                r[i + j] ^= self.mul(*pc, *qc);
+            }
+        }
+        r
+    }
+
+    fn poly_eval(&self, p: &[u8], x: u8) -> u8 {
+        let mut y = p[0];
+        for c in p.iter().skip(1) {
+            y = self.mul(y, x) ^ c;
+        }
+        y
+    }
+
+    fn poly_remainder(&self, dividend: &[u8], divisor: &[u8]) -> Vec<u8> {
+        // divisor is always monic here
+        let mut out = dividend.to_vec();
+        for i in 0..dividend.len() - (divisor.len() - 1) {
+            let coef = out[i];
+            if coef != 0 {
+                for j in 1..divisor.len() {
+                    out[i + j] ^= self.mul(divisor[j], coef);
+                }
+            }
+        }
+        out[dividend.len() - (divisor.len() - 1)..].to_vec()
+    }
+
+    fn generator_poly(&self, parity: usize) -> Vec<u8> {
+        let mut g = vec![1u8];
+        for i in 0..parity {
+            g = self.poly_mul(&g, &[1, self.pow(2, i as i32)]);
+        }
+        g
+    }
+
+    fn encode_block(&self, block: &[u8], generator: &[u8]) -> Vec<u8> {
+        let parity = generator.len() - 1;
+        let mut out = block.to_vec();
+        out.resize(block.len() + parity, 0);
+        for i in 0..block.len() {
+            let coef = out[i];
+            if coef != 0 {
+                for j in 1..generator.len() {
+                    out[i + j] ^= self.mul(generator[j], coef);
+                }
+            }
+        }
+        out[..block.len()].copy_from_slice(block);
+        out
+    }
+
+    fn syndromes(&self, block: &[u8], parity: usize) -> Vec<u8> {
+        // leading zero kept so the indices line up with the evaluator maths below
+        let mut synd = vec![0u8; parity + 1];
+        for i in 0..parity {
+            synd[i + 1] = self.poly_eval(block, self.pow(2, i as i32));
+        }
+        synd
+    }
+
+    fn error_locator(&self, synd: &[u8], parity: usize) -> Option<Vec<u8>> {
+        // Berlekamp-Massey
+        let mut err_loc = vec![1u8];
+        let mut old_loc = vec![1u8];
+        for i in 0..parity {
+            let k = i + 1;
+            let mut delta = synd[k];
+            for j in 1..err_loc.len() {
+                delta ^= self.mul(err_loc[err_loc.len() - 1 - j], synd[k - j]);
+            }
+            old_loc.push(0);
+            if delta != 0 {
+                if old_loc.len() > err_loc.len() {
+                    let new_loc = self.poly_scale(&old_loc, delta);
+                    old_loc = self.poly_scale(&err_loc, self.inverse(delta));
+                    err_loc = new_loc;
+                }
+                err_loc = self.poly_add(&err_loc, &self.poly_scale(&old_loc, delta));
+            }
+        }
+        let first = err_loc.iter().position(|c| *c != 0)?;
+        let err_loc = err_loc[first..].to_vec();
+        if (err_loc.len() - 1) * 2 > parity {
+            return None;
+        }
+        Some(err_loc)
+    }
+
+    fn error_positions(&self, err_loc: &[u8], block_len: usize) -> Option<Vec<usize>> {
+        // Chien search, over the (possibly shortened) block only
+        let reversed: Vec<u8> = err_loc.iter().rev().copied().collect();
+        let positions: Vec<usize> = (0..block_len)
+            .filter(|i| self.poly_eval(&reversed, self.pow(2, *i as i32)) == 0)
+            .map(|i| block_len - 1 - i)
+            .collect();
+        if positions.len() != err_loc.len() - 1 {
+            return None;
+        }
+        Some(positions)
+    }
+
+    fn correct_errors(&self, block: &mut [u8], synd: &[u8], positions: &[usize]) {
+        // Forney
+        let coef_pos: Vec<usize> = positions.iter().map(|p| block.len() - 1 - p).collect();
+        let mut err_loc = vec![1u8];
+        for p in coef_pos.iter() {
+            err_loc = self.poly_mul(&err_loc, &self.poly_add(&[1], &[self.pow(2, *p as i32), 0]));
+        }
+        let synd_rev: Vec<u8> = synd.iter().rev().copied().collect();
+        let mut modulus = vec![0u8; err_loc.len() + 1];
+        modulus[0] = 1;
+        let err_eval = self.poly_remainder(&self.poly_mul(&synd_rev, &err_loc), &modulus);
+
+        let x: Vec<u8> = coef_pos
+            .iter()
+            .map(|p| self.pow(2, -((FIELD_SIZE - p) as i32)))
+            .collect();
+        for (i, xi) in x.iter().enumerate() {
+            let xi_inv = self.inverse(*xi);
+            let mut err_loc_prime = 1u8;
+            for (j, xj) in x.iter().enumerate() {
+                if j != i {
+                    err_loc_prime = self.mul(err_loc_prime, 1 ^ self.mul(xi_inv, *xj));
+                }
+            }
+            let y = self.mul(*xi, self.poly_eval(&err_eval, xi_inv));
+            block[positions[i]] ^= self.div(y, err_loc_prime);
+        }
+    }
+
+    fn decode_block(&self, block: &mut [u8], parity: usize) -> Option<usize> {
+        let synd = self.syndromes(block, parity);
+        if synd.iter().all(|s| *s == 0) {
+            return Some(0);
+        }
+        let err_loc = self.error_locator(&synd, parity)?;
+        let positions = self.error_positions(&err_loc, block.len())?;
+        self.correct_errors(block, &synd, &positions);
+        if self.syndromes(block, parity).iter().any(|s| *s != 0) {
+            return None;
+        }
+        Some(positions.len())
+    }
+}
+
+fn block_lengths(data_len: u64, parity: u8) -> Vec<usize> {
+    let k = (FIELD_SIZE - parity as usize) as u64;
+    let full = data_len / k;
+    let mut lengths = vec![k as usize + parity as usize; full as usize];
+    if !data_len.is_multiple_of(k) {
+        lengths.push((data_len % k) as usize + parity as usize);
+    }
+    lengths
+}
+
+fn interleave_order(lengths: &[usize]) -> Vec<(usize, usize)> {
+    // (block, offset) of every stored byte, column by column across the blocks
+    let longest = lengths.iter().max().copied().unwrap_or(0);
+    let mut order = Vec::<(usize, usize)>::new();
+    for offset in 0..longest {
+        for (block, len) in lengths.iter().enumerate() {
+            if offset < *len {
+                order.push((block, offset));
+            }
+        }
+    }
+    order
+}
+
+pub fn encoded_len(data_len: u64, parity: u8) -> u64 {
+    let k = (FIELD_SIZE - parity as usize) as u64;
+    data_len.saturating_add(data_len.div_ceil(k).saturating_mul(parity as u64))
+}
+
+pub fn encode(data: &[u8], parity: u8) -> Vec<u8> {
+    let gf = Gf::new();
+    let generator = gf.generator_poly(parity as usize);
+    let blocks: Vec<Vec<u8>> = data
+        .chunks(FIELD_SIZE - parity as usize)
+        .map(|chunk| gf.encode_block(chunk, &generator))
+        .collect();
+    let lengths: Vec<usize> = blocks.iter().map(|b| b.len()).collect();
+
+    interleave_order(&lengths)
+        .iter()
+        .map(|(block, offset)| blocks[*block][*offset])
+        .collect()
+}
+
+pub fn decode(coded: &[u8], data_len: u64, parity: u8) -> Result<(Vec<u8>, usize), EccError> {
+    /*
+     *  Returns the corrected data and how many bytes had to be corrected.
+     *  `coded` must hold encoded_len(data_len, parity) bytes.
+     */
+    let gf = Gf::new();
+    let lengths = block_lengths(data_len, parity);
+    let mut blocks: Vec<Vec<u8>> = lengths.iter().map(|len| vec![0u8; *len]).collect();
+    for ((block, offset), byte) in interleave_order(&lengths).iter().zip(coded.iter()) {
+        blocks[*block][*offset] = *byte;
+    }
+
+    let mut corrected = 0;
+    let mut data = Vec::<u8>::with_capacity(data_len as usize);
+    for (i, block) in blocks.iter_mut().enumerate() {
+        corrected += gf
+            .decode_block(block, parity as usize)
+            .ok_or(EccError::Uncorrectable { block: i })?;
+        data.extend_from_slice(&block[..block.len() - parity as usize]);
+    }
+    Ok((data, corrected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoded_len() {
+        assert_eq!(encoded_len(0, 32), 0);
+        assert_eq!(encoded_len(100, 32), 132);
+        assert_eq!(encoded_len(223, 32), 255);
+        assert_eq!(encoded_len(224, 32), 255 + 33);
+    }
+
+    #[test]
+    fn test_roundtrip_with_errors() {
+        let data: Vec<u8> = (0..1000).map(|i| (i * 31 % 251) as u8).collect();
+        let mut coded = encode(&data, 16);
+        assert_eq!(coded.len() as u64, encoded_len(1000, 16));
+        assert_eq!(decode(&coded, 1000, 16), Ok((data.clone(), 0)));
+
+        // a burst of 30 bytes is spread over the 5 interleaved blocks, 6 errors each
+        for byte in coded.iter_mut().skip(100).take(30) {
+            *byte ^= 0x5a;
+        }
+        assert_eq!(decode(&coded, 1000, 16), Ok((data.clone(), 30)));
+
+        // 9 errors in every block is past what 16 parity bytes can fix
+        for byte in coded.iter_mut().skip(400).take(45) {
+            *byte ^= 0xa5;
+        }
+        assert!(decode(&coded, 1000, 16).is_err());
+    }
+}
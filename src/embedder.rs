@@ -0,0 +1,716 @@
+use std::io;
+use std::io::{Cursor, Read, Write};
+
+use crate::carrier::{CarrierReader, CarrierWriter, Carriers, Embedded};
+use crate::crypto;
+use crate::detect;
+use crate::error::{Result, StegaError};
+use crate::manip::{ManipulatorMode, PackedVertex, VertexManipulator};
+use crate::mesh::IndexedMesh;
+use crate::reader_writer::{
+    self, BinaryDecoder, BinaryEncoder, FrameError, FrameHeader, FramedEncoder,
+};
+
+/*
+ *  The encode and decode of the data tool, for programs that hold the mesh and payload in
+ *  memory rather than in files:
+ *
+ *      let stl = stlio::read_stl(&bytes)?;
+ *      let encoded = Embedder::new(&stl).bits(5).secret(b"key").scatter().embed(&payload)?;
+ *      let payload = Extractor::new(&encoded).secret(b"key").scatter().extract()?;
+ *
+ *  The payload goes in a frame (see reader_writer) unless another framing is asked for, and the
+ *  extractor finds the bit depth of a frame on its own when it isn't given one. Both sides have
+ *  to agree on the carriers, the secret and the scattering. Printed digits only exist in an
+ *  ASCII file, so that carrier is left to the tools.
+ *
+ *  embed is seal then write, and extract is read then open. The tools call the stages
+ *  themselves, on carriers they set up for any mesh format and with their report in between.
+ */
+const STL_COMPONENT_BITS: u8 = 32;
+
+// the secret and what it scatters, the tools set up their carriers with it too
+#[derive(Clone, Default)]
+pub(crate) struct Keying {
+    pub(crate) secret: Option<Vec<u8>>,
+    pub(crate) scatter: bool,
+    pub(crate) scatter_bits: bool,
+}
+
+impl Keying {
+    pub(crate) fn seed(&self) -> Result<Option<[u8; 32]>> {
+        if !self.scatter && !self.scatter_bits {
+            return Ok(None);
+        }
+        match &self.secret {
+            Some(secret) => Ok(Some(crypto::derive_seed(secret)?)),
+            None => Err(invalid_input("scattering needs a secret")),
+        }
+    }
+
+    pub(crate) fn manipulator<V: PackedVertex>(
+        &self,
+        mode: ManipulatorMode,
+        uniq_vertices: Vec<V>,
+        bits: u8,
+        seed: Option<[u8; 32]>,
+    ) -> Result<VertexManipulator<V>> {
+        let mut vman = VertexManipulator::new(mode, uniq_vertices, bits)?;
+        if let Some(seed) = seed {
+            if self.scatter {
+                vman = vman.with_scattered_vertices(seed);
+            }
+            if self.scatter_bits {
+                vman = vman.with_scattered_bits(seed);
+            }
+        }
+        Ok(vman)
+    }
+}
+
+fn invalid_input(message: &str) -> StegaError {
+    io::Error::new(io::ErrorKind::InvalidInput, message).into()
+}
+
+fn legacy_length(header: &[u8], available_bytes: u64) -> Result<u64> {
+    // the 8 byte length at the start of header, anything longer than the rest of the carriers is noise
+    let mut length = [0u8; 8];
+    length.copy_from_slice(&header[..reader_writer::HEADER_BYTES as usize]);
+    let length = u64::from_le_bytes(length);
+    let room = available_bytes.saturating_sub(reader_writer::HEADER_BYTES);
+    if length > room {
+        return Err(FrameError::ExceedsCapacity {
+            payload_bytes: length,
+            available_bytes: room,
+        }
+        .into());
+    }
+    Ok(length)
+}
+
+fn check_carriers(carriers: &Carriers) -> Result<()> {
+    if carriers.digits.is_some() {
+        return Err(invalid_input(
+            "the digits carrier needs an ASCII file, use the tools",
+        ));
+    }
+    Ok(())
+}
+
+/*
+ *  How the payload is laid out in the carriers. A frame says how long the payload is, how it
+ *  was compressed and sealed, and checks it. The legacy layout is the 8 byte length the first
+ *  version of the tools wrote, and a headerless payload is the bytes alone: its length has to
+ *  be given to the extractor, or comes sealed with the payload when there is a secret.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Framing {
+    #[default]
+    Frame,
+    Legacy,
+    Headerless,
+}
+
+impl Framing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Framing::Frame => "frame",
+            Framing::Legacy => "legacy",
+            Framing::Headerless => "headerless",
+        }
+    }
+}
+
+// a payload compressed and encrypted as asked, ready to be written into the carriers
+pub struct Sealed {
+    pub input_bytes: u64,
+    pub compressed_bytes: Option<u64>,
+    // what a legacy or headerless payload stores as its length, and a frame in its header
+    pub payload_bytes: u64,
+    // everything written to the carriers, header included
+    pub stored_bytes: u64,
+    data: Vec<u8>,
+    flags: u8,
+}
+
+pub struct Embedder<'a> {
+    stl: &'a stl::BinaryStlFile,
+    carriers: Carriers,
+    keying: Keying,
+    framing: Framing,
+    compress: bool,
+    ecc_parity: Option<u8>,
+}
+
+impl<'a> Embedder<'a> {
+    pub fn new(stl: &'a stl::BinaryStlFile) -> Embedder<'a> {
+        Embedder {
+            stl,
+            carriers: Carriers::default(),
+            keying: Keying::default(),
+            framing: Framing::Frame,
+            compress: false,
+            ecc_parity: None,
+        }
+    }
+
+    pub fn bits(mut self, bits: u8) -> Self {
+        self.carriers.vertex_bits = Some(bits);
+        self
+    }
+
+    pub fn carriers(mut self, carriers: Carriers) -> Self {
+        // replaces bits() too, leave vertex_bits None to carry nothing in the vertices
+        self.carriers = carriers;
+        self
+    }
+
+    pub fn secret(mut self, secret: &[u8]) -> Self {
+        self.keying.secret = Some(secret.to_vec());
+        self
+    }
+
+    pub fn scatter(mut self) -> Self {
+        self.keying.scatter = true;
+        self
+    }
+
+    pub fn scatter_bits(mut self) -> Self {
+        self.keying.scatter_bits = true;
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> Self {
+        // only a frame can say the payload was compressed or has parity
+        self.framing = framing;
+        self
+    }
+
+    pub fn compress(mut self) -> Self {
+        self.compress = true;
+        self
+    }
+
+    pub fn ecc(mut self, parity: u8) -> Self {
+        self.ecc_parity = Some(parity);
+        self
+    }
+
+    pub fn seal(&self, payload: &[u8]) -> Result<Sealed> {
+        // the first half of embed, for a caller that sets up its own carrier writer
+        self.check_framing()?;
+        let data = if self.compress {
+            reader_writer::compress(payload)?
+        } else {
+            payload.to_vec()
+        };
+        self.sealed(payload.len() as u64, data)
+    }
+
+    pub fn seal_repeated(
+        &self,
+        payload: &[u8],
+        times: u64,
+        available_bytes: u64,
+    ) -> Result<Sealed> {
+        /*
+         *  seal for payload times over, as the text tool repeats it. The repeats are never held
+         *  whole: without compression a size over available_bytes is refused before anything is
+         *  built, with it they are compressed one at a time and refused once they pass it.
+         */
+        self.check_framing()?;
+        let too_big = |needed_bytes| StegaError::InsufficientCapacity {
+            needed_bytes,
+            available_bytes,
+        };
+        let input_bytes = (payload.len() as u64)
+            .checked_mul(times)
+            .ok_or(too_big(u64::MAX))?;
+        let data = if self.compress {
+            reader_writer::compress_repeated(payload, times, available_bytes)?
+        } else if input_bytes <= available_bytes {
+            payload.repeat(times as usize)
+        } else {
+            return Err(too_big(input_bytes));
+        };
+        if data.len() as u64 > available_bytes {
+            return Err(too_big(data.len() as u64));
+        }
+        self.sealed(input_bytes, data)
+    }
+
+    fn check_framing(&self) -> Result<()> {
+        if self.framing != Framing::Frame && (self.compress || self.ecc_parity.is_some()) {
+            return Err(invalid_input(
+                "compression and parity need a frame to be recorded in",
+            ));
+        }
+        Ok(())
+    }
+
+    fn sealed(&self, input_bytes: u64, mut data: Vec<u8>) -> Result<Sealed> {
+        // data is already compressed when asked for, compression has to come before encryption
+        let mut flags: u8 = 0;
+        let mut compressed_bytes = None;
+        if self.compress {
+            compressed_bytes = Some(data.len() as u64);
+            flags |= reader_writer::FLAG_COMPRESSED;
+        }
+        if let Some(secret) = &self.keying.secret {
+            data = match self.framing {
+                Framing::Headerless => crypto::seal_with_length(secret, &data)?,
+                _ => crypto::seal(secret, &data)?,
+            };
+            flags |= reader_writer::FLAG_ENCRYPTED;
+        }
+        let payload_bytes = data.len() as u64;
+        let stored_bytes = match (self.framing, self.ecc_parity) {
+            (Framing::Frame, Some(parity)) => FrameHeader::new(flags, payload_bytes)
+                .with_ecc(parity)
+                .stored_bytes(),
+            (Framing::Frame, None) => FrameHeader::new(flags, payload_bytes).stored_bytes(),
+            (Framing::Legacy, _) => reader_writer::HEADER_BYTES + payload_bytes,
+            (Framing::Headerless, _) => payload_bytes,
+        };
+        Ok(Sealed {
+            input_bytes,
+            compressed_bytes,
+            payload_bytes,
+            stored_bytes,
+            data,
+            flags,
+        })
+    }
+
+    pub fn write(
+        &self,
+        sealed: Sealed,
+        mut writer: CarrierWriter,
+        mesh: &IndexedMesh,
+    ) -> Result<Embedded> {
+        // the second half, mesh has to be the one writer was set up on
+        reader_writer::ensure_capacity(sealed.stored_bytes, writer.capacity_bits() / 8)?;
+        let source = Cursor::new(sealed.data);
+        let length = sealed.payload_bytes;
+        match (self.framing, self.ecc_parity) {
+            (Framing::Frame, Some(parity)) => writer.write_feed(&mut FramedEncoder::with_ecc(
+                source,
+                length,
+                sealed.flags,
+                parity,
+            )?)?,
+            (Framing::Frame, None) => {
+                writer.write_feed(&mut FramedEncoder::new(source, length, sealed.flags))?
+            }
+            (Framing::Legacy, _) => writer.write_feed(&mut BinaryEncoder::new(source, length))?,
+            (Framing::Headerless, _) => {
+                writer.write_feed(&mut BinaryEncoder::headerless(source, length))?
+            }
+        }
+        Ok(writer.finish(&self.stl.header, mesh))
+    }
+
+    pub fn embed(&self, payload: &[u8]) -> Result<stl::BinaryStlFile> {
+        check_carriers(&self.carriers)?;
+        let sealed = self.seal(payload)?;
+        let mesh = IndexedMesh::from_triangles(&self.stl.triangles);
+        let seed = self.keying.seed()?;
+        let writer = self.carriers.writer(&mesh, |v, bits| {
+            self.keying
+                .manipulator(ManipulatorMode::WRITE, v, bits, seed)
+        })?;
+        Ok(self.write(sealed, writer, &mesh)?.stl)
+    }
+
+    pub fn embed_from<R: Read>(&self, reader: &mut R) -> Result<stl::BinaryStlFile> {
+        // the payload is everything reader has left
+        let mut payload = Vec::<u8>::new();
+        reader.read_to_end(&mut payload)?;
+        self.embed(&payload)
+    }
+
+    pub fn embed_to<W: Write>(&self, payload: &[u8], writer: &mut W) -> Result<()> {
+        // as a binary STL
+        let stl = self.embed(payload)?;
+        stl::write_stl(writer, &stl)?;
+        Ok(())
+    }
+}
+
+// a payload as read from the carriers, before it is opened
+pub struct Stored {
+    pub framing: Framing,
+    pub header: Option<FrameHeader>,
+    pub corrected: usize,
+    pub encrypted: bool,
+    pub compressed: bool,
+    payload: Vec<u8>,
+    // a headerless payload sealed with its length
+    sealed: Option<crypto::LengthPrefix>,
+}
+
+impl Stored {
+    pub fn payload_bytes(&self) -> u64 {
+        match &self.sealed {
+            Some(length) => length.body_bytes(),
+            None => self.payload.len() as u64,
+        }
+    }
+}
+
+pub struct Extractor<'a> {
+    stl: &'a stl::BinaryStlFile,
+    carriers: Carriers,
+    keying: Keying,
+    framing: Framing,
+    length: Option<u64>,
+}
+
+impl<'a> Extractor<'a> {
+    pub fn new(stl: &'a stl::BinaryStlFile) -> Extractor<'a> {
+        Extractor {
+            stl,
+            carriers: Carriers::default(),
+            keying: Keying::default(),
+            framing: Framing::Frame,
+            length: None,
+        }
+    }
+
+    pub fn bits(mut self, bits: u8) -> Self {
+        self.carriers.vertex_bits = Some(bits);
+        self
+    }
+
+    pub fn carriers(mut self, carriers: Carriers) -> Self {
+        // with vertex_bits None the bit depth is detected
+        self.carriers = carriers;
+        self
+    }
+
+    pub fn secret(mut self, secret: &[u8]) -> Self {
+        self.keying.secret = Some(secret.to_vec());
+        self
+    }
+
+    pub fn scatter(mut self) -> Self {
+        self.keying.scatter = true;
+        self
+    }
+
+    pub fn scatter_bits(mut self) -> Self {
+        self.keying.scatter_bits = true;
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> Self {
+        // the bit depth is only detected for a frame
+        self.framing = framing;
+        self
+    }
+
+    pub fn length(mut self, payload_bytes: u64) -> Self {
+        // of a headerless payload without a secret
+        self.length = Some(payload_bytes);
+        self
+    }
+
+    pub fn read(&self, mut reader: CarrierReader) -> Result<Stored> {
+        // the first half of extract, for a caller that sets up its own carrier reader
+        let available_bytes = reader.capacity_bits() / 8;
+        let mut decoder = BinaryDecoder::new();
+        let mut stored = Stored {
+            framing: self.framing,
+            header: None,
+            corrected: 0,
+            encrypted: self.keying.secret.is_some(),
+            compressed: false,
+            payload: Vec::new(),
+            sealed: None,
+        };
+        match self.framing {
+            Framing::Frame => {
+                let read = |count| reader.read_bytes(&mut decoder, count);
+                let frame = reader_writer::read_frame(read, available_bytes)?;
+                stored.encrypted = frame.header.has_flag(reader_writer::FLAG_ENCRYPTED);
+                stored.compressed = frame.header.has_flag(reader_writer::FLAG_COMPRESSED);
+                stored.corrected = frame.corrected;
+                stored.header = Some(frame.header);
+                stored.payload = frame.payload;
+            }
+            Framing::Legacy => {
+                let header = reader.read_bytes(&mut decoder, reader_writer::HEADER_BYTES);
+                let length = legacy_length(&header, available_bytes)?;
+                stored.payload = reader.read_bytes(&mut decoder, length);
+            }
+            Framing::Headerless => match (&self.keying.secret, self.length) {
+                (Some(secret), _) => {
+                    let prefix =
+                        reader.read_bytes(&mut decoder, crypto::LENGTH_PREFIX_BYTES as u64);
+                    let length = crypto::open_length(secret, &prefix)?;
+                    reader_writer::ensure_capacity(
+                        crypto::LENGTH_PREFIX_BYTES as u64 + length.body_bytes(),
+                        available_bytes,
+                    )?;
+                    stored.payload = reader.read_bytes(&mut decoder, length.body_bytes());
+                    stored.sealed = Some(length);
+                }
+                (None, Some(length)) => {
+                    reader_writer::ensure_capacity(length, available_bytes)?;
+                    stored.payload = reader.read_bytes(&mut decoder, length);
+                }
+                (None, None) => {
+                    return Err(invalid_input(
+                        "a headerless payload needs its length, or a secret if it was sealed",
+                    ));
+                }
+            },
+        }
+        Ok(stored)
+    }
+
+    pub fn open(&self, stored: Stored) -> Result<Vec<u8>> {
+        // the second half: decrypt, then decompress
+        let payload = match (&self.keying.secret, stored.sealed, stored.encrypted) {
+            (_, Some(length), _) => length.open_body(&stored.payload)?,
+            (Some(secret), None, true) => crypto::open(secret, &stored.payload)?,
+            (None, None, true) => return Err(StegaError::Encrypted),
+            (_, None, false) => stored.payload,
+        };
+        if stored.compressed {
+            Ok(reader_writer::decompress(&payload)?)
+        } else {
+            Ok(payload)
+        }
+    }
+
+    pub fn extract(&self) -> Result<Vec<u8>> {
+        // compression and encryption are read from the frame flags
+        check_carriers(&self.carriers)?;
+        let mesh = IndexedMesh::from_triangles(&self.stl.triangles);
+        let seed = self.keying.seed()?;
+        let reader_at = |bits: u8| -> Result<CarrierReader> {
+            let carriers = Carriers {
+                vertex_bits: Some(bits),
+                ..self.carriers
+            };
+            carriers.reader(&mesh, |v, bits| {
+                self.keying
+                    .manipulator(ManipulatorMode::READ, v, bits, seed)
+            })
+        };
+        let bits = match (self.carriers.vertex_bits, self.framing) {
+            (Some(bits), _) => bits,
+            (None, Framing::Frame) => detect::detect_bit_depth(STL_COMPONENT_BITS, reader_at)?
+                .ok_or(FrameError::NoPayload)?,
+            (None, _) => return Err(StegaError::BitDepthRequired),
+        };
+        self.open(self.read(reader_at(bits)?)?)
+    }
+
+    pub fn extract_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.extract()?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stlio;
+
+    fn grid_stl(side: u32) -> stl::BinaryStlFile {
+        let point = |x: u32, y: u32| [x as f32 * 0.37, y as f32 * 0.41, (x * y) as f32 * 0.1 + 5.0];
+        let mut triangles = Vec::<stl::Triangle>::new();
+        for y in 0..side - 1 {
+            for x in 0..side - 1 {
+                for (v1, v2, v3) in [
+                    (point(x, y), point(x + 1, y), point(x + 1, y + 1)),
+                    (point(x, y), point(x + 1, y + 1), point(x, y + 1)),
+                ] {
+                    triangles.push(stl::Triangle {
+                        normal: [0.0, 0.0, 1.0],
+                        v1,
+                        v2,
+                        v3,
+                        attr_byte_count: 0,
+                    });
+                }
+            }
+        }
+        stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: [0u8; 80],
+                num_triangles: triangles.len() as u32,
+            },
+            triangles,
+        }
+    }
+
+    fn read_at(stl: &stl::BinaryStlFile, bits: u8) -> Result<Stored> {
+        let extractor = Extractor::new(stl).bits(bits);
+        let mesh = IndexedMesh::from_triangles(&stl.triangles);
+        let reader = extractor.carriers.reader(&mesh, |v, bits| {
+            extractor
+                .keying
+                .manipulator(ManipulatorMode::READ, v, bits, None)
+        })?;
+        extractor.read(reader)
+    }
+
+    #[test]
+    fn test_roundtrip_in_memory() {
+        let stl = grid_stl(20);
+        let payload: Vec<u8> = (0..300u32).map(|i| (i % 7) as u8).collect();
+        let embedder = Embedder::new(&stl)
+            .bits(5)
+            .secret(b"hunter2")
+            .scatter()
+            .scatter_bits()
+            .compress()
+            .ecc(8);
+        let mut bytes = Vec::<u8>::new();
+        embedder.embed_to(&payload, &mut bytes).unwrap();
+
+        let encoded = stlio::read_stl(&bytes).unwrap();
+        let extracted = Extractor::new(&encoded)
+            .secret(b"hunter2")
+            .scatter()
+            .scatter_bits()
+            .extract()
+            .unwrap();
+        assert_eq!(extracted, payload);
+
+        let repeated = Embedder::new(&stl).bits(5).compress();
+        let sealed = repeated.seal_repeated(b"again ", 1000, 1000).unwrap();
+        assert_eq!(sealed.input_bytes, 6000);
+        let mesh = IndexedMesh::from_triangles(&stl.triangles);
+        let writer = repeated
+            .carriers
+            .writer(&mesh, |v, bits| {
+                VertexManipulator::new(ManipulatorMode::WRITE, v, bits)
+            })
+            .unwrap();
+        let encoded = repeated.write(sealed, writer, &mesh).unwrap().stl;
+        assert_eq!(
+            Extractor::new(&encoded).bits(5).extract().unwrap(),
+            b"again ".repeat(1000)
+        );
+
+        let plain = Embedder::new(&stl).bits(3).embed(b"plain").unwrap();
+        assert_eq!(Extractor::new(&plain).bits(3).extract().unwrap(), b"plain");
+    }
+
+    #[test]
+    fn test_framings() {
+        let stl = grid_stl(12);
+        let payload = b"the same bytes every way".to_vec();
+        for framing in [Framing::Legacy, Framing::Headerless] {
+            for secret in [None, Some(b"k".as_slice())] {
+                let mut embedder = Embedder::new(&stl).bits(4).framing(framing);
+                let mut extractor = Extractor::new(&stl).bits(4).framing(framing);
+                if let Some(secret) = secret {
+                    embedder = embedder.secret(secret);
+                    extractor = extractor.secret(secret);
+                } else {
+                    extractor = extractor.length(payload.len() as u64);
+                }
+                let encoded = embedder.embed_from(&mut payload.as_slice()).unwrap();
+                let extracted = Extractor {
+                    stl: &encoded,
+                    ..extractor
+                }
+                .extract();
+                assert_eq!(extracted.unwrap(), payload);
+            }
+        }
+
+        // only a frame records compression, and only a frame's bit depth is detected
+        let legacy = Embedder::new(&stl).bits(4).framing(Framing::Legacy);
+        assert!(legacy.compress().embed(&payload).is_err());
+        assert!(matches!(
+            Extractor::new(&stl).framing(Framing::Legacy).extract(),
+            Err(StegaError::BitDepthRequired)
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let stl = grid_stl(6);
+        assert!(matches!(
+            Embedder::new(&stl).bits(1).embed(&[0u8; 100]),
+            Err(StegaError::InsufficientCapacity { .. })
+        ));
+        assert!(Embedder::new(&stl).bits(8).scatter().embed(b"x").is_err());
+
+        let encoded = Embedder::new(&stl)
+            .bits(8)
+            .secret(b"k")
+            .embed(b"x")
+            .unwrap();
+        assert!(matches!(
+            Extractor::new(&encoded).bits(8).extract(),
+            Err(StegaError::Encrypted)
+        ));
+        assert!(matches!(
+            Extractor::new(&stl).extract(),
+            Err(StegaError::Frame(FrameError::NoPayload))
+        ));
+
+        // nothing embedded is no payload at any given depth, round coordinates included
+        let mut round = grid_stl(6);
+        for t in round.triangles.iter_mut() {
+            for v in [&mut t.v1, &mut t.v2, &mut t.v3] {
+                v.iter_mut().for_each(|c| *c = c.round());
+            }
+        }
+        for (clean, bits) in [(&stl, 4), (&stl, 8), (&round, 4), (&round, 8)] {
+            assert!(matches!(
+                Extractor::new(clean).bits(bits).extract(),
+                Err(StegaError::Frame(FrameError::NoPayload))
+            ));
+        }
+
+        // a repeated payload is measured before it is built, compressed or not
+        for embedder in [Embedder::new(&stl), Embedder::new(&stl).compress()] {
+            assert!(matches!(
+                embedder.seal_repeated(b"hello", 100_000_000_000, 300),
+                Err(StegaError::InsufficientCapacity { .. })
+            ));
+        }
+        assert!(matches!(
+            Embedder::new(&stl).seal_repeated(b"hello", u64::MAX, 300),
+            Err(StegaError::InsufficientCapacity { .. })
+        ));
+
+        // a headerless length can't be more than the mesh holds either
+        let headerless = Extractor::new(&stl)
+            .bits(8)
+            .framing(Framing::Headerless)
+            .length(99_999_999_999);
+        assert!(matches!(
+            headerless.extract(),
+            Err(StegaError::InsufficientCapacity { .. })
+        ));
+
+        // the frame magic is all that tells a payload from noise, at a wrong depth too
+        let framed = Embedder::new(&stl).bits(5).embed(b"framed").unwrap();
+        assert_eq!(read_at(&framed, 5).unwrap().payload, b"framed");
+        for (mesh, bits) in [(&stl, 5), (&framed, 4), (&framed, 6)] {
+            assert!(matches!(
+                read_at(mesh, bits),
+                Err(StegaError::Frame(FrameError::NoPayload))
+            ));
+        }
+
+        // a frame read as a legacy length is far more than the mesh holds
+        let framed = Embedder::new(&stl).bits(8).embed(b"x").unwrap();
+        assert!(matches!(
+            Extractor::new(&framed)
+                .bits(8)
+                .framing(Framing::Legacy)
+                .extract(),
+            Err(StegaError::Frame(FrameError::ExceedsCapacity { .. }))
+        ));
+    }
+}
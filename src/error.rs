@@ -0,0 +1,152 @@
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+use crate::crypto::CryptoError;
+use crate::header::HeaderError;
+use crate::reader_writer::FrameError;
+
+/*
+ *  Everything the library can fail with, so a caller embedding it gets a Result back instead
+ *  of a panic. The errors of each part (frames, encryption, headers) keep their own types and
+ *  are wrapped here as they are.
+ *
+ *  The tools print the message and exit with exit_code:
+ *   - 1 reading or writing a file failed, or anything else
+ *   - 2 the command line was wrong (clap), or its options don't go together
+ *   - 3 the mesh can't carry the payload as asked: bit depth, empty mesh, not enough room,
+ *     a carrier it doesn't have
+ *   - 4 no payload was found, or it was damaged or sealed with another key
+ */
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_UNSUITABLE_MESH: u8 = 3;
+pub const EXIT_NO_PAYLOAD: u8 = 4;
+
+#[derive(Debug)]
+pub enum StegaError {
+    Io(io::Error),
+    Mesh {
+        path: String,
+        source: io::Error,
+    },
+    BitDepth {
+        bits: u8,
+        max_bits: u8,
+    },
+    BitDepthRequired,
+    EmptyMesh,
+    CarrierUnavailable(String),
+    InsufficientCapacity {
+        needed_bytes: u64,
+        available_bytes: u64,
+    },
+    PayloadTruncated,
+    Encrypted,
+    Frame(FrameError),
+    Crypto(CryptoError),
+    Header(HeaderError),
+    Usage(String),
+}
+
+pub type Result<T> = std::result::Result<T, StegaError>;
+
+impl StegaError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            StegaError::BitDepthRequired | StegaError::Usage(_) => EXIT_USAGE,
+            StegaError::BitDepth { .. }
+            | StegaError::EmptyMesh
+            | StegaError::CarrierUnavailable(_)
+            | StegaError::InsufficientCapacity { .. }
+            | StegaError::Header(_) => EXIT_UNSUITABLE_MESH,
+            StegaError::Frame(_) | StegaError::Crypto(_) | StegaError::Encrypted => EXIT_NO_PAYLOAD,
+            StegaError::Io(_) | StegaError::Mesh { .. } | StegaError::PayloadTruncated => {
+                EXIT_FAILURE
+            }
+        }
+    }
+}
+
+impl fmt::Display for StegaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StegaError::Io(e) => write!(f, "{}", e),
+            StegaError::Mesh { path, source } => write!(f, "can't read {}: {}", path, source),
+            StegaError::BitDepth { bits, max_bits } => write!(
+                f,
+                "a bit depth of {} is out of range, coordinates here take 1 to {}",
+                bits, max_bits
+            ),
+            StegaError::BitDepthRequired => write!(
+                f,
+                "the vertices carrier needs a bit depth, only a framed payload's is found when decoding"
+            ),
+            StegaError::EmptyMesh => write!(f, "the mesh has no vertices to carry data"),
+            StegaError::CarrierUnavailable(reason) => write!(f, "{}", reason),
+            StegaError::InsufficientCapacity {
+                needed_bytes,
+                available_bytes,
+            } => write!(
+                f,
+                "the payload needs {} bytes but the mesh only holds {}",
+                needed_bytes, available_bytes
+            ),
+            StegaError::PayloadTruncated => write!(f, "payload ended before its declared length"),
+            StegaError::Encrypted => {
+                write!(f, "the payload is encrypted, it needs a secret to open")
+            }
+            StegaError::Frame(e) => write!(f, "{}", e),
+            StegaError::Crypto(e) => write!(f, "{}", e),
+            StegaError::Header(e) => write!(f, "{}", e),
+            StegaError::Usage(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StegaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StegaError::Io(e) | StegaError::Mesh { source: e, .. } => Some(e),
+            StegaError::Frame(e) => Some(e),
+            StegaError::Crypto(e) => Some(e),
+            StegaError::Header(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StegaError {
+    fn from(e: io::Error) -> StegaError {
+        StegaError::Io(e)
+    }
+}
+
+impl From<FrameError> for StegaError {
+    fn from(e: FrameError) -> StegaError {
+        StegaError::Frame(e)
+    }
+}
+
+impl From<CryptoError> for StegaError {
+    fn from(e: CryptoError) -> StegaError {
+        StegaError::Crypto(e)
+    }
+}
+
+impl From<HeaderError> for StegaError {
+    fn from(e: HeaderError) -> StegaError {
+        StegaError::Header(e)
+    }
+}
+
+pub fn exit_code(result: Result<()>) -> ExitCode {
+    // for the tools' main: the message on stderr, and the exit code that goes with it
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crc32fast::Hasher;
+use std::fmt;
+
+/*
+ *  The 80 byte header of a binary STL is free text that nothing reads: exporters put a banner
+ *  in it and pad the rest with spaces or zeros.
+ *
+ *  A small hidden record can sit right after the banner, behind a zero byte so anything that
+ *  shows the header as a string stops before it:
+ *   - 1 byte zero terminator
+ *   - 1 byte flags, 1 byte payload length
+ *   - 8 bytes key fingerprint, if flagged
+ *   - the payload, then a CRC32 of flags through payload so stray bytes aren't misread
+ *  The rest of the header is zero filled.
+ */
+pub const STL_HEADER_BYTES: usize = 80;
+pub const FINGERPRINT_BYTES: usize = 8;
+const RECORD_OVERHEAD: usize = 7;
+const FLAG_FINGERPRINT: u8 = 0b0000_0001;
+
+// banners in the style of common exporters, none of them may start with "solid"
+const PRESETS: [(&str, &str); 3] = [
+    ("solidworks", "SOLIDWORKS binary STL file"),
+    ("fusion", "Binary STL created by Autodesk Fusion 360"),
+    ("blender", "Exported from Blender-3.6.5"),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum HeaderError {
+    BannerTooLong {
+        banner_bytes: usize,
+    },
+    HiddenTooLarge {
+        hidden_bytes: usize,
+        available_bytes: usize,
+    },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::BannerTooLong { banner_bytes } => write!(
+                f,
+                "banner is {} bytes, the header only holds {}",
+                banner_bytes, STL_HEADER_BYTES
+            ),
+            HeaderError::HiddenTooLarge {
+                hidden_bytes,
+                available_bytes,
+            } => write!(
+                f,
+                "{} bytes to hide, only {} bytes left in the header after the banner",
+                hidden_bytes, available_bytes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+#[derive(Debug, PartialEq)]
+pub struct HiddenRecord {
+    pub fingerprint: Option<[u8; FINGERPRINT_BYTES]>,
+    pub payload: Vec<u8>,
+}
+
+pub fn preset_names() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, _)| *name).collect()
+}
+
+pub fn preset_banner(name: &str) -> Option<&'static str> {
+    PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == name)
+        .map(|(_, banner)| *banner)
+}
+
+fn banner_bytes(header: &[u8; STL_HEADER_BYTES]) -> usize {
+    // up to the first zero, without any space padding
+    let end = header
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(STL_HEADER_BYTES);
+    header[..end]
+        .iter()
+        .rposition(|b| *b != b' ')
+        .map(|p| p + 1)
+        .unwrap_or(0)
+}
+
+pub fn banner(header: &[u8; STL_HEADER_BYTES]) -> String {
+    header[..banner_bytes(header)]
+        .iter()
+        .map(|b| match b {
+            0x20..=0x7e => *b as char,
+            _ => '.',
+        })
+        .collect()
+}
+
+pub fn with_banner(banner: &str) -> Result<[u8; STL_HEADER_BYTES], HeaderError> {
+    let bytes = banner.as_bytes();
+    if bytes.len() > STL_HEADER_BYTES {
+        return Err(HeaderError::BannerTooLong {
+            banner_bytes: bytes.len(),
+        });
+    }
+    let mut header = [b' '; STL_HEADER_BYTES];
+    header[..bytes.len()].copy_from_slice(bytes);
+    Ok(header)
+}
+
+pub fn hidden_capacity(header: &[u8; STL_HEADER_BYTES], with_fingerprint: bool) -> usize {
+    let mut used = banner_bytes(header) + RECORD_OVERHEAD;
+    if with_fingerprint {
+        used += FINGERPRINT_BYTES;
+    }
+    STL_HEADER_BYTES.saturating_sub(used)
+}
+
+pub fn hide(header: &mut [u8; STL_HEADER_BYTES], record: &HiddenRecord) -> Result<(), HeaderError> {
+    let available_bytes = hidden_capacity(header, record.fingerprint.is_some());
+    if record.payload.len() > available_bytes {
+        return Err(HeaderError::HiddenTooLarge {
+            hidden_bytes: record.payload.len(),
+            available_bytes,
+        });
+    }
+
+    let mut body = Vec::<u8>::new();
+    match record.fingerprint {
+        Some(fingerprint) => {
+            body.push(FLAG_FINGERPRINT);
+            body.push(record.payload.len() as u8);
+            body.extend_from_slice(&fingerprint);
+        }
+        None => {
+            body.push(0);
+            body.push(record.payload.len() as u8);
+        }
+    }
+    body.extend_from_slice(&record.payload);
+    let mut hasher = Hasher::new();
+    hasher.update(&body);
+    body.extend_from_slice(&hasher.finalize().to_le_bytes());
+
+    let start = banner_bytes(header);
+    header[start..].fill(0);
+    header[start + 1..start + 1 + body.len()].copy_from_slice(&body);
+    Ok(())
+}
+
+pub fn find_hidden(header: &[u8; STL_HEADER_BYTES]) -> Option<HiddenRecord> {
+    let start = banner_bytes(header);
+    let body = header.get(start + 1..)?;
+    if header.get(start) != Some(&0) || body.len() < RECORD_OVERHEAD - 1 {
+        return None;
+    }
+    let flags = body[0];
+    let payload_bytes = body[1] as usize;
+    let fingerprint_bytes = match flags {
+        0 => 0,
+        FLAG_FINGERPRINT => FINGERPRINT_BYTES,
+        _ => return None,
+    };
+    let end = 2 + fingerprint_bytes + payload_bytes;
+    let trailer = body.get(end..end + 4)?;
+
+    let mut hasher = Hasher::new();
+    hasher.update(&body[..end]);
+    if hasher.finalize().to_le_bytes() != trailer {
+        return None;
+    }
+
+    let fingerprint = match fingerprint_bytes {
+        0 => None,
+        _ => {
+            let mut fingerprint = [0u8; FINGERPRINT_BYTES];
+            fingerprint.copy_from_slice(&body[2..2 + FINGERPRINT_BYTES]);
+            Some(fingerprint)
+        }
+    };
+    Some(HiddenRecord {
+        fingerprint,
+        payload: body[2 + fingerprint_bytes..end].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner() {
+        let header = with_banner(preset_banner("blender").unwrap()).unwrap();
+        assert_eq!(banner(&header), "Exported from Blender-3.6.5");
+        assert!(with_banner(&"x".repeat(81)).is_err());
+        assert_eq!(banner(&[0u8; STL_HEADER_BYTES]), "");
+    }
+
+    #[test]
+    fn test_hidden_record() {
+        let mut header = with_banner("pipeline test").unwrap();
+        assert_eq!(find_hidden(&header), None);
+
+        let record = HiddenRecord {
+            fingerprint: Some([1, 2, 3, 4, 5, 6, 7, 8]),
+            payload: b"build 42".to_vec(),
+        };
+        hide(&mut header, &record).unwrap();
+        assert_eq!(banner(&header), "pipeline test");
+        assert_eq!(find_hidden(&header), Some(record));
+
+        header[30] ^= 1;
+        assert_eq!(find_hidden(&header), None);
+
+        let too_big = HiddenRecord {
+            fingerprint: None,
+            payload: vec![0u8; 61],
+        };
+        assert!(hide(&mut header, &too_big).is_err());
+    }
+}
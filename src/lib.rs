@@ -1,3 +1,22 @@
+pub mod attributes;
+pub mod carrier;
+pub mod cli;
+pub mod crypto;
+pub mod detect;
+pub mod digits;
+pub mod ecc;
+pub mod embedder;
+pub mod error;
+pub mod header;
 pub mod manip;
+pub mod mesh;
+pub mod normals;
+pub mod obj;
+pub mod permute;
+pub mod ply;
 pub mod reader_writer;
+pub mod rotate;
 pub mod stlio;
+pub mod stream;
+pub mod threemf;
+pub mod zip;
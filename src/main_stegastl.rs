@@ -0,0 +1,8 @@
+use lib::cli;
+use lib::error;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let matches = cli::command().get_matches();
+    error::exit_code(cli::run(&matches))
+}
@@ -1,37 +1,29 @@
-use std::collections::{HashMap, HashSet};
-use std::iter::Cycle;
+use rand::RngCore;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use std::hash::Hash;
 use std::panic;
-use std::vec::IntoIter;
 
-pub fn get_uniq_vertices_as_ordered_bits(t: &Vec<stl::Triangle>, m: &mut Vec<u128>) {
-    let mut uniq_set = HashSet::<u128>::new();
+use crate::error::{Result, StegaError};
 
-    for tri in t.iter() {
-        let v1full: u128 = get_vertex_as_bits(tri, 0);
-        let v2full: u128 = get_vertex_as_bits(tri, 1);
-        let v3full: u128 = get_vertex_as_bits(tri, 2);
-
-        if !uniq_set.contains(&v1full) {
-            uniq_set.insert(v1full.clone());
-            m.push(v1full);
-        }
-        if !uniq_set.contains(&v2full) {
-            uniq_set.insert(v2full.clone());
-            m.push(v2full);
-        }
-        if !uniq_set.contains(&v3full) {
-            uniq_set.insert(v3full.clone());
-            m.push(v3full);
-        }
+pub fn check_bit_depth(bits: u8, component_bits: u8) -> Result<()> {
+    if bits == 0 || bits > component_bits {
+        return Err(StegaError::BitDepth {
+            bits,
+            max_bits: component_bits,
+        });
     }
+    Ok(())
 }
 
-fn validate_bit_range(bits: u8) {
-    assert!(bits > 0 && bits <= 32);
+fn validate_bit_range(bits: u8, component_bits: u8) {
+    // the shift tables are only built for a bit depth check_bit_depth already let through
+    assert!(bits > 0 && bits <= component_bits);
 }
 
 pub fn get_bit_shifts(bits: u8) -> Vec<u8> {
-    validate_bit_range(bits);
+    validate_bit_range(bits, 32);
     let mut target_bits = Vec::<u8>::new();
     //create a walkable list of which bits in a u128 to touch, in order
     let shifts: [u8; 3] = [96, 64, 32]; //x, y, z
@@ -41,7 +33,6 @@ pub fn get_bit_shifts(bits: u8) -> Vec<u8> {
         }
     }
 
-    let target_bits = target_bits;
     target_bits
 }
 
@@ -51,34 +42,24 @@ pub fn get_keep_mask(bits: &u8) -> u128 {
     mask <<= 32;
     let shifts = get_bit_shifts(*bits);
     for b in shifts.iter() {
-        mask = mask & !(1 << b);
+        mask &= !(1 << b);
     }
     mask
 }
 
 pub fn get_vertex_as_bits(t: &stl::Triangle, i: u8) -> u128 {
-    let vx: u128;
-    let vy: u128;
-    let vz: u128;
-
     match i {
-        0 => {
-            vx = t.v1[0].to_bits().into();
-            vy = t.v1[1].to_bits().into();
-            vz = t.v1[2].to_bits().into();
-        }
-        1 => {
-            vx = t.v2[0].to_bits().into();
-            vy = t.v2[1].to_bits().into();
-            vz = t.v2[2].to_bits().into();
-        }
-        2 => {
-            vx = t.v3[0].to_bits().into();
-            vy = t.v3[1].to_bits().into();
-            vz = t.v3[2].to_bits().into();
-        }
+        0 => get_position_as_bits(&t.v1),
+        1 => get_position_as_bits(&t.v2),
+        2 => get_position_as_bits(&t.v3),
         _ => panic!("vertex out of range"),
     }
+}
+
+pub fn get_position_as_bits(v: &[f32; 3]) -> u128 {
+    let vx: u128 = v[0].to_bits().into();
+    let vy: u128 = v[1].to_bits().into();
+    let vz: u128 = v[2].to_bits().into();
 
     let mut bits: u128 = vx;
     bits <<= 32;
@@ -95,77 +76,270 @@ pub fn get_vertex_from_bits(bits: &u128) -> [f32; 3] {
     let vy: u32 = (bits >> 64 & 0xFFFFFFFF) as u32;
     let vx: u32 = (bits >> 96 & 0xFFFFFFFF) as u32;
 
-    return [f32::from_bits(vx), f32::from_bits(vy), f32::from_bits(vz)];
+    [f32::from_bits(vx), f32::from_bits(vy), f32::from_bits(vz)]
 }
 
-pub fn generate_transformed_stl(
-    orig_stl: &stl::BinaryStlFile,
-    vert_trans_map: &HashMap<u128, u128>,
-) -> stl::BinaryStlFile {
-    let outheader: stl::BinaryStlHeader = stl::BinaryStlHeader {
-        header: orig_stl.header.header,
-        num_triangles: orig_stl.header.num_triangles,
-    };
-    let mut tris: Vec<stl::Triangle> = Vec::<stl::Triangle>::new();
-    for src_tri in orig_stl.triangles.iter() {
-        let v1bits: u128 = get_vertex_as_bits(src_tri, 0);
-        let v2bits: u128 = get_vertex_as_bits(src_tri, 1);
-        let v3bits: u128 = get_vertex_as_bits(src_tri, 2);
-        let newv1bits: u128 = *vert_trans_map.get(&v1bits).unwrap_or(&v1bits);
-        let newv2bits: u128 = *vert_trans_map.get(&v2bits).unwrap_or(&v2bits);
-        let newv3bits: u128 = *vert_trans_map.get(&v3bits).unwrap_or(&v3bits);
-        let new_tri = stl::Triangle {
-            normal: src_tri.normal,
-            attr_byte_count: src_tri.attr_byte_count,
-            v1: get_vertex_from_bits(&newv1bits),
-            v2: get_vertex_from_bits(&newv2bits),
-            v3: get_vertex_from_bits(&newv3bits),
-        };
-        tris.push(new_tri);
+pub fn copy_triangle(tri: &stl::Triangle) -> stl::Triangle {
+    // stl::Triangle is not Clone
+    stl::Triangle {
+        normal: tri.normal,
+        v1: tri.v1,
+        v2: tri.v2,
+        v3: tri.v3,
+        attr_byte_count: tri.attr_byte_count,
+    }
+}
+
+pub fn get_double_position_as_bits(v: &[f64; 3]) -> [u64; 3] {
+    v.map(f64::to_bits)
+}
+
+pub fn get_double_position_from_bits(bits: &[u64; 3]) -> [f64; 3] {
+    bits.map(f64::from_bits)
+}
+
+/*
+ *  The vertex channel only needs a few things from a vertex: which bits it may write, and
+ *  reading and writing one of them. Single precision vertices are the three f32 bit patterns,
+ *  double precision ones (from PLY) the three f64 bit patterns, with 52 mantissa bits per
+ *  coordinate to play with instead of 23.
+ */
+pub trait PackedVertex: Copy + Default + Eq + Hash + Send + Sync {
+    // bits per coordinate, the most a bit depth can be
+    const COMPONENT_BITS: u8;
+
+    fn bit_shifts(bits: u8) -> Vec<u8>;
+    fn bit(&self, shift: u8) -> u8;
+    fn with_bit(self, shift: u8, bit: u8) -> Self;
+    fn position(&self) -> [f64; 3];
+    fn into_set(vertices: Vec<Self>) -> VertexSet;
+
+    fn masked(self, bits: u8) -> Self {
+        Self::bit_shifts(bits)
+            .iter()
+            .fold(self, |v, shift| v.with_bit(*shift, 0))
+    }
+}
+
+impl PackedVertex for [u32; 3] {
+    const COMPONENT_BITS: u8 = 32;
+
+    fn bit_shifts(bits: u8) -> Vec<u8> {
+        // shift c * 32 + b is bit b of coordinate c, walked x, y, z and highest bit first,
+        // the same order get_bit_shifts walks a packed u128 in
+        validate_bit_range(bits, 32);
+        (0..3u8)
+            .flat_map(|c| (0..bits).rev().map(move |b| c * 32 + b))
+            .collect()
+    }
+
+    fn bit(&self, shift: u8) -> u8 {
+        (self[(shift / 32) as usize] >> (shift % 32) & 1) as u8
+    }
+
+    fn with_bit(mut self, shift: u8, bit: u8) -> Self {
+        let component = &mut self[(shift / 32) as usize];
+        *component = *component & !(1 << (shift % 32)) | (bit as u32) << (shift % 32);
+        self
+    }
+
+    fn position(&self) -> [f64; 3] {
+        self.map(|c| f32::from_bits(c) as f64)
+    }
+
+    fn into_set(vertices: Vec<Self>) -> VertexSet {
+        VertexSet::Single(vertices)
+    }
+}
+
+impl PackedVertex for [u64; 3] {
+    const COMPONENT_BITS: u8 = 64;
+
+    fn bit_shifts(bits: u8) -> Vec<u8> {
+        // shift c * 64 + b is bit b of coordinate c, walked x, y, z and highest bit first
+        validate_bit_range(bits, 64);
+        (0..3u8)
+            .flat_map(|c| (0..bits).rev().map(move |b| c * 64 + b))
+            .collect()
+    }
+
+    fn bit(&self, shift: u8) -> u8 {
+        (self[(shift / 64) as usize] >> (shift % 64) & 1) as u8
+    }
+
+    fn with_bit(mut self, shift: u8, bit: u8) -> Self {
+        let component = &mut self[(shift / 64) as usize];
+        *component = *component & !(1 << (shift % 64)) | (bit as u64) << (shift % 64);
+        self
+    }
+
+    fn position(&self) -> [f64; 3] {
+        self.map(f64::from_bits)
+    }
+
+    fn into_set(vertices: Vec<Self>) -> VertexSet {
+        VertexSet::Double(vertices)
+    }
+}
+
+pub enum VertexSet {
+    Single(Vec<[u32; 3]>),
+    Double(Vec<[u64; 3]>),
+}
+
+impl VertexSet {
+    pub fn len(&self) -> usize {
+        match self {
+            VertexSet::Single(vertices) => vertices.len(),
+            VertexSet::Double(vertices) => vertices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn component_bits(&self) -> u8 {
+        match self {
+            VertexSet::Single(_) => <[u32; 3]>::COMPONENT_BITS,
+            VertexSet::Double(_) => <[u64; 3]>::COMPONENT_BITS,
+        }
+    }
+
+    pub fn displacement(&self, written: &VertexSet) -> Displacement {
+        match (self, written) {
+            (VertexSet::Single(before), VertexSet::Single(after)) => displacement(before, after),
+            (VertexSet::Double(before), VertexSet::Double(after)) => displacement(before, after),
+            _ => Displacement::default(),
+        }
     }
-    let outstl: stl::BinaryStlFile = stl::BinaryStlFile {
-        header: outheader,
-        triangles: tris,
-    };
+}
+
+/*
+ *  How far writing moved a vertex list: how many vertices changed, and the furthest any one
+ *  of them moved, in the model's own units.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Displacement {
+    pub vertices_touched: u64,
+    pub max_distance: f64,
+}
 
-    outstl
+pub fn displacement<V: PackedVertex>(before: &[V], after: &[V]) -> Displacement {
+    // both lists in the same order
+    let mut result = Displacement::default();
+    for (b, a) in before.iter().zip(after.iter()).filter(|(b, a)| b != a) {
+        let (b, a) = (b.position(), a.position());
+        let distance = (0..3).map(|c| (a[c] - b[c]).powi(2)).sum::<f64>().sqrt();
+        result.vertices_touched += 1;
+        result.max_distance = result.max_distance.max(distance);
+    }
+    result
 }
 
-pub fn get_available_bits(bits: u8, uniq_vert: &Vec<u128>) -> u64 {
+pub fn get_available_bits<V>(bits: u8, uniq_vert: &[V]) -> u64 {
     (uniq_vert.len() * (bits as usize) * 3) as u64
 }
 
+fn keyed_shuffle<T>(items: &mut [T], rng: &mut ChaCha20Rng) {
+    // plain Fisher-Yates driven by next_u64, so the permutation only depends on the
+    // ChaCha20 stream and not on how a given rand release implements shuffling
+    for i in (1..items.len()).rev() {
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+pub fn scatter_vertices<V>(uniq_vert: &mut [V], seed: [u8; 32]) {
+    /*
+     *  Reorders the unique vertex list with a keyed permutation.
+     *  Fed to a VertexManipulator, the payload lands on vertices spread over the whole mesh
+     *  instead of the first N encountered in the file.
+     *  Decoding with the same seed reproduces the same order.
+     */
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    keyed_shuffle(uniq_vert, &mut rng);
+}
+
 #[derive(PartialEq)]
 pub enum ManipulatorMode {
     READ,
     WRITE,
 }
 
-pub struct VertexManipulator {
+/*
+ *  Walks the vertex list one vertex at a time, a bit at a time, and writes in place: the list
+ *  that comes back from into_vertices is in the order it went in, with the payload applied.
+ *  Scattering only changes which index is visited next.
+ *  Past the last vertex, reads give 0 and writes go nowhere.
+ */
+pub struct VertexManipulator<V: PackedVertex> {
     mode: ManipulatorMode,
     bits_encoding: u8,
-    vertex_iter: IntoIter<u128>,
-    shift_iter: Cycle<IntoIter<u8>>,
-    last_shift: u8,
-    current_vertex: u128,
-    current_vertex_src: u128,
+    vertices: Vec<V>,
+    order: Vec<u32>,
+    visit: usize,
+    shifts: Vec<u8>,
+    shift_pos: usize,
+    shift_rng: Option<ChaCha20Rng>,
 }
 
-impl VertexManipulator {
-    pub fn new(mode: ManipulatorMode, vertices: Vec<u128>, bits: u8) -> VertexManipulator {
-        let sb = get_bit_shifts(bits);
-        let last_shift_bit = *sb.last().unwrap();
-        let sbi = sb.into_iter().cycle();
-        let mut vi = vertices.into_iter();
-        let nv = vi.next().unwrap();
-        VertexManipulator {
-            mode: mode,
+impl<V: PackedVertex> VertexManipulator<V> {
+    pub fn new(mode: ManipulatorMode, vertices: Vec<V>, bits: u8) -> Result<VertexManipulator<V>> {
+        check_bit_depth(bits, V::COMPONENT_BITS)?;
+        if vertices.is_empty() {
+            return Err(StegaError::EmptyMesh);
+        }
+        let sb = V::bit_shifts(bits);
+        let order = (0..vertices.len() as u32).collect();
+        Ok(VertexManipulator {
+            mode,
             bits_encoding: bits,
-            vertex_iter: vi,
-            shift_iter: sbi,
-            last_shift: last_shift_bit,
-            current_vertex: nv,
-            current_vertex_src: nv.clone(),
+            vertices,
+            order,
+            visit: 0,
+            shifts: sb,
+            shift_pos: 0,
+            shift_rng: None,
+        })
+    }
+
+    pub fn with_scattered_vertices(mut self, seed: [u8; 32]) -> VertexManipulator<V> {
+        // the same permutation scatter_vertices gives the list, applied to the visit order
+        scatter_vertices(&mut self.order, seed);
+        self
+    }
+
+    pub fn with_scattered_bits(mut self, seed: [u8; 32]) -> VertexManipulator<V> {
+        // bit positions within every vertex are visited in a keyed order, reshuffled per vertex
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        keyed_shuffle(&mut self.shifts, &mut rng);
+        self.shift_rng = Some(rng);
+        self
+    }
+
+    pub fn into_vertices(self) -> Vec<V> {
+        self.vertices
+    }
+
+    fn current_index(&self) -> Option<usize> {
+        self.order.get(self.visit).map(|i| *i as usize)
+    }
+
+    fn next_shift(&mut self) -> (u8, bool) {
+        let shift = self.shifts[self.shift_pos];
+        self.shift_pos += 1;
+        let is_last_vertex_bit = self.shift_pos == self.shifts.len();
+        if is_last_vertex_bit {
+            self.shift_pos = 0;
+        }
+        (shift, is_last_vertex_bit)
+    }
+
+    fn advance_vertex(&mut self) {
+        self.visit += 1;
+        if let Some(rng) = self.shift_rng.as_mut() {
+            keyed_shuffle(&mut self.shifts, rng);
         }
     }
 
@@ -173,64 +347,141 @@ impl VertexManipulator {
         if self.mode != ManipulatorMode::READ {
             return 0;
         }
-        let next_shift = self.shift_iter.next().unwrap();
-        let next_bit: u8 = (self.current_vertex >> next_shift & 1) as u8;
-        let is_last_vertex_bit = next_shift == self.last_shift;
+        let (next_shift, is_last_vertex_bit) = self.next_shift();
+        let next_bit: u8 = match self.current_index() {
+            Some(i) => self.vertices[i].bit(next_shift),
+            None => 0,
+        };
         if is_last_vertex_bit {
-            self.current_vertex = self.vertex_iter.next().unwrap_or(0 as u128);
-            self.current_vertex_src = self.current_vertex.clone();
+            self.advance_vertex();
         }
         next_bit
     }
 
-    pub fn set_next_bit_in_vertex(&mut self, bit: u8) -> (bool, u128, u128) {
+    pub fn set_next_bit_in_vertex(&mut self, bit: u8) -> bool {
+        // true when that was the last bit of the vertex
         if self.mode != ManipulatorMode::WRITE {
-            return (false, 0, 0);
+            return false;
         }
-        let next_shift = self.shift_iter.next().unwrap();
-        let mut next_bit: u128 = bit as u128;
-        next_bit <<= next_shift;
-        let next_bit = next_bit;
-        //mask off the current vertex and write the bit
-        let mask = 1 << next_shift;
-        self.current_vertex = self.current_vertex & !mask | next_bit;
-
-        //if just wrote the last bit in this vertex(or we're done), send the original and the
-        //result back to calling code for mapping
-        let mut is_last_vertex_bit = false;
-        let vertex_result_src: u128 = self.current_vertex_src.clone();
-        let vertex_result_res: u128 = self.current_vertex.clone();
-
-        if next_shift == self.last_shift {
-            is_last_vertex_bit = true;
-            self.current_vertex = self.vertex_iter.next().unwrap_or(0 as u128);
-            self.current_vertex_src = self.current_vertex.clone();
+        let (next_shift, is_last_vertex_bit) = self.next_shift();
+        if let Some(i) = self.current_index() {
+            self.vertices[i] = self.vertices[i].with_bit(next_shift, bit);
+        }
+        if is_last_vertex_bit {
+            self.advance_vertex();
         }
+        is_last_vertex_bit
+    }
 
-        (is_last_vertex_bit, vertex_result_src, vertex_result_res)
+    fn whole_vertices(&mut self, bit_count: usize) -> (usize, usize, Option<Vec<u8>>) {
+        /*
+         *  How many whole vertices the next bit_count bits cover, where they start in the visit
+         *  order, and their bit orders laid end to end when they differ per vertex. Moves past
+         *  them, so what comes after carries on as if they had been walked bit by bit.
+         */
+        let per_vertex = self.shifts.len();
+        let first = self.visit;
+        let count = (bit_count / per_vertex).min(self.order.len().saturating_sub(first));
+        let table = match self.shift_rng {
+            Some(_) => {
+                let mut table = Vec::<u8>::with_capacity(count * per_vertex);
+                for _ in 0..count {
+                    table.extend_from_slice(&self.shifts);
+                    self.advance_vertex();
+                }
+                Some(table)
+            }
+            None => {
+                self.visit += count;
+                None
+            }
+        };
+        (first, count, table)
     }
 
-    pub fn print_masked_bits(&self) {
-        let vbits = format!("{:0128b}", self.current_vertex);
-        let mask = get_keep_mask(&self.bits_encoding);
-        let masked_bits: String = vbits
-            .chars()
+    pub fn write_bits(&mut self, bits: &[u8]) {
+        /*
+         *  Same as set_next_bit_in_vertex for each bit in turn. Every whole vertex takes the
+         *  next 3 * bits of the payload, so once the bit order of each one is known up front
+         *  they are all written in parallel. A vertex already started, and whatever is left
+         *  over at the end, go bit by bit.
+         */
+        if self.mode != ManipulatorMode::WRITE {
+            return;
+        }
+        let mut bits = bits;
+        while self.shift_pos != 0 && !bits.is_empty() {
+            self.set_next_bit_in_vertex(bits[0]);
+            bits = &bits[1..];
+        }
+
+        let per_vertex = self.shifts.len();
+        let same_shifts = self.shifts.clone();
+        let (first, count, table) = self.whole_vertices(bits.len());
+        let visits = &self.order[first..first + count];
+        let vertices = &self.vertices;
+        let written: Vec<V> = visits
+            .par_iter()
             .enumerate()
-            .map(|(i, c)| if (mask >> (127 - i)) & 1 == 1 { ' ' } else { c })
-            .collect();
-        let guide: String = (0..128)
-            .map(|i| {
-                if i == 32 || i == 64 || i == 96 {
-                    '_'
-                } else {
-                    ' '
-                }
+            .map(|(k, i)| {
+                let shifts = match &table {
+                    Some(table) => &table[k * per_vertex..(k + 1) * per_vertex],
+                    None => &same_shifts[..],
+                };
+                let payload = &bits[k * per_vertex..(k + 1) * per_vertex];
+                shifts
+                    .iter()
+                    .zip(payload.iter())
+                    .fold(vertices[*i as usize], |v, (shift, bit)| {
+                        v.with_bit(*shift, *bit)
+                    })
             })
             .collect();
+        for (i, vertex) in visits.iter().zip(written) {
+            self.vertices[*i as usize] = vertex;
+        }
 
-        println!("{}", guide);
-        println!("{}", vbits);
-        println!("{}", masked_bits);
+        for bit in bits[count * per_vertex..].iter() {
+            self.set_next_bit_in_vertex(*bit);
+        }
+    }
+
+    pub fn read_bits(&mut self, bit_count: usize) -> Vec<u8> {
+        // same as next_bit_from_vertex bit_count times, whole vertices read in parallel
+        if self.mode != ManipulatorMode::READ {
+            return vec![0; bit_count];
+        }
+        let mut output = Vec::<u8>::with_capacity(bit_count);
+        while self.shift_pos != 0 && output.len() < bit_count {
+            output.push(self.next_bit_from_vertex());
+        }
+
+        let per_vertex = self.shifts.len();
+        let same_shifts = self.shifts.clone();
+        let (first, count, table) = self.whole_vertices(bit_count - output.len());
+        let visits = &self.order[first..first + count];
+        let vertices = &self.vertices;
+        output.par_extend(visits.par_iter().enumerate().flat_map_iter(|(k, i)| {
+            let shifts = match &table {
+                Some(table) => &table[k * per_vertex..(k + 1) * per_vertex],
+                None => &same_shifts[..],
+            };
+            let vertex = vertices[*i as usize];
+            shifts.iter().map(move |shift| vertex.bit(*shift))
+        }));
+
+        while output.len() < bit_count {
+            output.push(self.next_bit_from_vertex());
+        }
+        output
+    }
+}
+
+impl VertexManipulator<[u32; 3]> {
+    pub fn print_masked_bits(&self) {
+        if let Some(i) = self.current_index() {
+            crate::mesh::print_vertex_bits(&self.vertices[i], self.bits_encoding);
+        }
     }
 }
 
@@ -253,6 +504,27 @@ mod tests {
     }
 
     #[test]
+    fn test_manipulator_errors() {
+        let single = |bits| VertexManipulator::new(ManipulatorMode::READ, vec![[1u32; 3]], bits);
+        assert!(matches!(
+            single(0),
+            Err(StegaError::BitDepth { bits: 0, .. })
+        ));
+        assert!(matches!(
+            single(33),
+            Err(StegaError::BitDepth { max_bits: 32, .. })
+        ));
+        assert!(single(32).is_ok());
+        assert!(VertexManipulator::new(ManipulatorMode::READ, vec![[1u64; 3]], 40).is_ok());
+        assert!(matches!(
+            VertexManipulator::<[u32; 3]>::new(ManipulatorMode::WRITE, Vec::new(), 4),
+            Err(StegaError::EmptyMesh)
+        ));
+    }
+
+    #[test]
+    // as the baseline wrote it
+    #[allow(clippy::unnecessary_cast)]
     fn test_get_keep_mask() {
         let result = get_keep_mask(&(1 as u8));
         let maskfor32: u128 = 0b11111111_11111111_11111111_11111110;
@@ -270,6 +542,8 @@ mod tests {
     }
 
     #[test]
+    // as the baseline wrote it
+    #[allow(clippy::redundant_field_names)]
     fn test_vertex_transform() {
         let norm: [f32; 3] = [0.0, 0.0, 0.0];
         let v1: [f32; 3] = [1.0, 2.0, 3.0];
@@ -295,55 +569,96 @@ mod tests {
     }
 
     #[test]
-    fn test_get_unique_vertices() {
-        let norm: [f32; 3] = [0.0, 0.0, 0.0];
-        let v1: [f32; 3] = [1.0, 2.0, 3.0];
-        let v2: [f32; 3] = [4.0, 5.0, 6.0];
-        let v3: [f32; 3] = [7.0, 8.0, 9.0];
+    fn test_scattered_roundtrip() {
+        let seed = [7u8; 32];
+        let vertices: Vec<[u32; 3]> = (1..=20).map(|v| [v, v, v]).collect();
+        let mut scattered = vertices.clone();
+        scatter_vertices(&mut scattered, seed);
+        assert_ne!(scattered, vertices);
+        let mut again = vertices.clone();
+        scatter_vertices(&mut again, seed);
+        assert_eq!(scattered, again);
+
+        let payload: Vec<u8> = (0..60).map(|i| (i * 7 % 3 == 0) as u8).collect();
+        let mut writer = VertexManipulator::new(ManipulatorMode::WRITE, vertices.clone(), 2)
+            .unwrap()
+            .with_scattered_vertices(seed)
+            .with_scattered_bits(seed);
+        for bit in payload.iter() {
+            writer.set_next_bit_in_vertex(*bit);
+        }
+        let encoded = writer.into_vertices();
+
+        // visiting in scattered order is the same as writing to a scattered list
+        let mut listed = VertexManipulator::new(ManipulatorMode::WRITE, scattered, 2)
+            .unwrap()
+            .with_scattered_bits(seed);
+        for bit in payload.iter() {
+            listed.set_next_bit_in_vertex(*bit);
+        }
+        let mut encoded_scattered = encoded.clone();
+        scatter_vertices(&mut encoded_scattered, seed);
+        assert_eq!(listed.into_vertices(), encoded_scattered);
+
+        let mut reader = VertexManipulator::new(ManipulatorMode::READ, encoded, 2)
+            .unwrap()
+            .with_scattered_vertices(seed)
+            .with_scattered_bits(seed);
+        let result: Vec<u8> = payload
+            .iter()
+            .map(|_| reader.next_bit_from_vertex())
+            .collect();
+        assert_eq!(result, payload);
+    }
 
-        let v4: [f32; 3] = [10.0, 11.0, 12.0];
-        let v5: [f32; 3] = v2;
-        let v6: [f32; 3] = [12.0, 13.0, 14.0];
-
-        let v7: [f32; 3] = v4;
-        let v8: [f32; 3] = v6;
-        let v9: [f32; 3] = [15.0, 16.0, 17.0];
-
-        let mut tris: Vec<stl::Triangle> = vec![
-            stl::Triangle {
-                normal: norm,
-                attr_byte_count: 0,
-                v1: v1,
-                v2: v2,
-                v3: v3,
-            },
-            stl::Triangle {
-                normal: norm,
-                attr_byte_count: 0,
-                v1: v4,
-                v2: v5,
-                v3: v6,
-            },
-            stl::Triangle {
-                normal: norm,
-                attr_byte_count: 0,
-                v1: v7,
-                v2: v8,
-                v3: v9,
-            },
-        ];
-
-        let mut result: Vec<u128> = vec![];
-        get_uniq_vertices_as_ordered_bits(&mut tris, &mut result);
-        let expected: Vec<u128> = vec![
-            get_vertex_as_bits(&tris[0], 0),
-            get_vertex_as_bits(&tris[0], 1),
-            get_vertex_as_bits(&tris[0], 2),
-            get_vertex_as_bits(&tris[1], 0),
-            get_vertex_as_bits(&tris[1], 2),
-            get_vertex_as_bits(&tris[2], 2),
-        ];
-
-        assert_eq!(result, expected);
+    #[test]
+    fn test_bulk_matches_bit_by_bit() {
+        let seed = [3u8; 32];
+        let vertices: Vec<[u32; 3]> = (0..50u32).map(|v| [v * 7, v * 11, v * 13]).collect();
+        // starts and ends part way through a vertex and runs past the last one
+        let payload: Vec<u8> = (0..50 * 9 + 20).map(|i| (i * 5 % 7 < 3) as u8).collect();
+        let make = |mode, vertices: Vec<[u32; 3]>, scatter: bool| {
+            let vman = VertexManipulator::new(mode, vertices, 3)
+                .unwrap()
+                .with_scattered_vertices(seed);
+            if scatter {
+                vman.with_scattered_bits(seed)
+            } else {
+                vman
+            }
+        };
+
+        for scatter in [false, true] {
+            let mut one_by_one = make(ManipulatorMode::WRITE, vertices.clone(), scatter);
+            for bit in payload.iter() {
+                one_by_one.set_next_bit_in_vertex(*bit);
+            }
+            let mut bulk = make(ManipulatorMode::WRITE, vertices.clone(), scatter);
+            bulk.write_bits(&payload[..4]);
+            bulk.write_bits(&payload[4..]);
+            let encoded = bulk.into_vertices();
+            assert_eq!(encoded, one_by_one.into_vertices());
+
+            let mut reader = make(ManipulatorMode::READ, encoded, scatter);
+            let mut result = reader.read_bits(5);
+            result.extend(reader.read_bits(payload.len() - 5));
+            assert_eq!(result[..50 * 9], payload[..50 * 9]);
+            assert!(result[50 * 9..].iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn test_displacement() {
+        let before: Vec<[u32; 3]> = vec![[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]
+            .into_iter()
+            .map(|v| v.map(f32::to_bits))
+            .collect();
+        let mut after = before.clone();
+        after[0] = [1.0f32, 2.0, 3.5].map(f32::to_bits);
+        after[2] = [7.0f32, 8.0 + 3.0, 9.0 + 4.0].map(f32::to_bits);
+        let moved = displacement(&before, &after);
+        assert_eq!(moved.vertices_touched, 2);
+        assert_eq!(moved.max_distance, 5.0);
+        assert_eq!(displacement(&before, &before), Displacement::default());
     }
 }
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use crate::manip::{self, PackedVertex};
+
+/*
+ *  An STL repeats every shared vertex in each triangle that uses it, six times over on a
+ *  typical mesh. IndexedMesh keeps each distinct position once, in the order a walk over the
+ *  triangles in file order first meets it, and every triangle as three indices into that list.
+ *
+ *  It's built once at load. After that a carrier writes vertices by index and the triangles are
+ *  rebuilt from the indices, so nothing has to look a vertex up by its value again. The normal
+ *  and attribute bytes of each triangle are kept alongside, so the mesh is the only copy of the
+ *  triangles a loaded file needs; to_triangles gives them back for the carriers that work on
+ *  whole triangles.
+ *
+ *  Positions are told apart by their bit patterns, so 0.0 and -0.0 are different vertices.
+ */
+#[derive(Default)]
+pub struct IndexedMesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub attr_byte_counts: Vec<u16>,
+}
+
+pub fn index_positions<V: PackedVertex>(positions: &[V]) -> (Vec<V>, Vec<u32>) {
+    // the distinct positions in the order first seen, and where each position went in that list
+    let mut first_seen = HashMap::<V, u32>::new();
+    let mut uniq = Vec::<V>::new();
+    let indices = positions
+        .iter()
+        .map(|v| {
+            *first_seen.entry(*v).or_insert_with(|| {
+                uniq.push(*v);
+                (uniq.len() - 1) as u32
+            })
+        })
+        .collect();
+    (uniq, indices)
+}
+
+impl IndexedMesh {
+    pub fn from_triangles(triangles: &[stl::Triangle]) -> IndexedMesh {
+        // one pass, each corner looked up by its bit pattern as it's met
+        let mut first_seen = HashMap::<[u32; 3], u32>::new();
+        let mut mesh = IndexedMesh {
+            vertices: Vec::new(),
+            triangles: Vec::with_capacity(triangles.len()),
+            normals: Vec::with_capacity(triangles.len()),
+            attr_byte_counts: Vec::with_capacity(triangles.len()),
+        };
+        for t in triangles {
+            let indices = [t.v1, t.v2, t.v3].map(|v| {
+                *first_seen.entry(v.map(f32::to_bits)).or_insert_with(|| {
+                    mesh.vertices.push(v);
+                    (mesh.vertices.len() - 1) as u32
+                })
+            });
+            mesh.triangles.push(indices);
+            mesh.normals.push(t.normal);
+            mesh.attr_byte_counts.push(t.attr_byte_count);
+        }
+        mesh
+    }
+
+    pub fn to_triangles(&self) -> Vec<stl::Triangle> {
+        (0..self.triangles.len())
+            .map(|t| self.triangle(t, &self.vertices, 0))
+            .collect()
+    }
+
+    fn triangle(&self, t: usize, vertices: &[[f32; 3]], rotation: usize) -> stl::Triangle {
+        let corner = |i: usize| vertices[self.triangles[t][(rotation + i) % 3] as usize];
+        stl::Triangle {
+            normal: self.normals[t],
+            v1: corner(0),
+            v2: corner(1),
+            v3: corner(2),
+            attr_byte_count: self.attr_byte_counts[t],
+        }
+    }
+
+    pub fn vertex_bits(&self) -> Vec<[u32; 3]> {
+        self.vertices.iter().map(|v| v.map(f32::to_bits)).collect()
+    }
+
+    pub fn first_seen_order(&self, triangle_order: &[usize], starts: &[u8]) -> Vec<u32> {
+        /*
+         *  Vertex indices in the order a walk over the triangles first meets them, taking the
+         *  triangles in triangle_order and each one from its start corner (0 past the end of
+         *  starts). Walking in file order from corner 0 gives 0, 1, 2 and so on.
+         */
+        let mut seen = vec![false; self.vertices.len()];
+        let mut order = Vec::<u32>::with_capacity(self.vertices.len());
+        for (position, t) in triangle_order.iter().enumerate() {
+            let start = starts.get(position).copied().unwrap_or(0) as usize;
+            for corner in 0..3 {
+                let index = self.triangles[*t][(start + corner) % 3];
+                if !seen[index as usize] {
+                    seen[index as usize] = true;
+                    order.push(index);
+                }
+            }
+        }
+        order
+    }
+
+    pub fn write_triangles(
+        &self,
+        vertices: &[[f32; 3]],
+        triangle_order: &[usize],
+        rotations: &[u8],
+    ) -> Vec<stl::Triangle> {
+        /*
+         *  The triangles in triangle_order, with their corners taken from vertices by index. A
+         *  triangle with an entry in rotations is cycled to start at that corner, the normal and
+         *  attribute bytes come along as they are.
+         */
+        triangle_order
+            .iter()
+            .enumerate()
+            .map(|(position, t)| {
+                let rotation = rotations.get(position).copied().unwrap_or(0) as usize;
+                self.triangle(*t, vertices, rotation)
+            })
+            .collect()
+    }
+}
+
+pub fn file_order(count: usize) -> Vec<usize> {
+    (0..count).collect()
+}
+
+pub fn single_vertices(bits: &[[u32; 3]]) -> Vec<[f32; 3]> {
+    bits.iter().map(|v| v.map(f32::from_bits)).collect()
+}
+
+pub fn print_vertex_bits(vertex: &[u32; 3], bits: u8) {
+    // the three coordinates packed side by side, the low bits a carrier can use shown alone
+    let packed: u128 = manip::get_position_as_bits(&vertex.map(f32::from_bits));
+    let vbits = format!("{:0128b}", packed);
+    let mask = manip::get_keep_mask(&bits);
+    let masked_bits: String = vbits
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if (mask >> (127 - i)) & 1 == 1 { ' ' } else { c })
+        .collect();
+    let guide: String = (0..128)
+        .map(|i| {
+            if i == 32 || i == 64 || i == 96 {
+                '_'
+            } else {
+                ' '
+            }
+        })
+        .collect();
+
+    println!("{}", guide);
+    println!("{}", vbits);
+    println!("{}", masked_bits);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_mesh() {
+        let norm: [f32; 3] = [0.0, 0.0, 0.0];
+        let v1: [f32; 3] = [1.0, 2.0, 3.0];
+        let v2: [f32; 3] = [4.0, 5.0, 6.0];
+        let v3: [f32; 3] = [7.0, 8.0, 9.0];
+        let v4: [f32; 3] = [10.0, 11.0, 12.0];
+        let v6: [f32; 3] = [12.0, 13.0, 14.0];
+        let v9: [f32; 3] = [15.0, 16.0, 17.0];
+        let triangle = |v1, v2, v3| stl::Triangle {
+            normal: norm,
+            attr_byte_count: 0,
+            v1,
+            v2,
+            v3,
+        };
+        let tris = vec![
+            triangle(v1, v2, v3),
+            triangle(v4, v2, v6),
+            triangle(v4, v6, v9),
+        ];
+
+        let mesh = IndexedMesh::from_triangles(&tris);
+        assert_eq!(mesh.vertices, vec![v1, v2, v3, v4, v6, v9]);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [3, 1, 4], [3, 4, 5]]);
+        assert_eq!(
+            mesh.first_seen_order(&file_order(3), &[]),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        // backwards, with the last triangle walked from its third corner
+        assert_eq!(
+            mesh.first_seen_order(&[2, 1, 0], &[2]),
+            vec![5, 3, 4, 1, 0, 2]
+        );
+
+        let mut moved = mesh.vertices.clone();
+        moved[1] = [-4.0, 5.0, 6.0];
+        let written = mesh.write_triangles(&moved, &[1, 0, 2], &[1]);
+        assert_eq!(written[0].v1, [-4.0, 5.0, 6.0]);
+        assert_eq!(written[0].v3, v4);
+        assert_eq!(written[1].v2, [-4.0, 5.0, 6.0]);
+        assert_eq!(written[2].v1, v4);
+        assert!(mesh.to_triangles() == tris);
+    }
+
+    #[test]
+    fn test_get_unique_vertices() {
+        let norm: [f32; 3] = [0.0, 0.0, 0.0];
+        let v1: [f32; 3] = [1.0, 2.0, 3.0];
+        let v2: [f32; 3] = [4.0, 5.0, 6.0];
+        let v3: [f32; 3] = [7.0, 8.0, 9.0];
+
+        let v4: [f32; 3] = [10.0, 11.0, 12.0];
+        let v5: [f32; 3] = v2;
+        let v6: [f32; 3] = [12.0, 13.0, 14.0];
+
+        let v7: [f32; 3] = v4;
+        let v8: [f32; 3] = v6;
+        let v9: [f32; 3] = [15.0, 16.0, 17.0];
+
+        let tris: Vec<stl::Triangle> = vec![
+            stl::Triangle {
+                normal: norm,
+                attr_byte_count: 0,
+                v1,
+                v2,
+                v3,
+            },
+            stl::Triangle {
+                normal: norm,
+                attr_byte_count: 0,
+                v1: v4,
+                v2: v5,
+                v3: v6,
+            },
+            stl::Triangle {
+                normal: norm,
+                attr_byte_count: 0,
+                v1: v7,
+                v2: v8,
+                v3: v9,
+            },
+        ];
+
+        let result: Vec<u128> = IndexedMesh::from_triangles(&tris)
+            .vertices
+            .iter()
+            .map(manip::get_position_as_bits)
+            .collect();
+        let expected: Vec<u128> = vec![
+            manip::get_vertex_as_bits(&tris[0], 0),
+            manip::get_vertex_as_bits(&tris[0], 1),
+            manip::get_vertex_as_bits(&tris[0], 2),
+            manip::get_vertex_as_bits(&tris[1], 0),
+            manip::get_vertex_as_bits(&tris[1], 2),
+            manip::get_vertex_as_bits(&tris[2], 2),
+        ];
+
+        assert_eq!(result, expected);
+    }
+}
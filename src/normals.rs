@@ -0,0 +1,149 @@
+/*
+ *  Slicers recompute facet normals from the winding of the vertices, so the 96 bits of normal
+ *  stored with every triangle never reach the printer.
+ *
+ *  The low `bits` bits of each normal component carry the payload, x then y then z, highest of
+ *  those bits first, the same walk the vertex channel uses. At 32 the normal is replaced
+ *  outright; below that the normal still points roughly the right way to anything that looks.
+ */
+
+fn component_shifts(bits: u8) -> Vec<u8> {
+    assert!(bits > 0 && bits <= 32);
+    (0..3).flat_map(|_| (0..bits).rev()).collect()
+}
+
+pub fn capacity_bits(triangles: &[stl::Triangle], bits: u8) -> u64 {
+    triangles.len() as u64 * 3 * bits as u64
+}
+
+pub struct NormalWriter {
+    normals: Vec<[u32; 3]>,
+    shifts: Vec<u8>,
+    position: u64,
+}
+
+impl NormalWriter {
+    pub fn new(triangles: &[stl::Triangle], bits: u8) -> NormalWriter {
+        NormalWriter {
+            normals: triangles
+                .iter()
+                .map(|t| t.normal.map(|c| c.to_bits()))
+                .collect(),
+            shifts: component_shifts(bits),
+            position: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.normals.len() as u64 * self.shifts.len() as u64
+    }
+
+    pub fn pushed_bits(&self) -> u64 {
+        self.position
+    }
+
+    pub fn push_bit(&mut self, bit: u8) {
+        if self.position >= self.capacity_bits() {
+            return;
+        }
+        let per_triangle = self.shifts.len() as u64;
+        let triangle = (self.position / per_triangle) as usize;
+        let slot = (self.position % per_triangle) as usize;
+        let component = slot / (self.shifts.len() / 3);
+        let shift = self.shifts[slot];
+
+        let value = &mut self.normals[triangle][component];
+        *value = *value & !(1 << shift) | (bit as u32) << shift;
+        self.position += 1;
+    }
+
+    pub fn apply(&self, triangles: &mut [stl::Triangle]) {
+        // triangles must be in the order the writer was created with
+        for (tri, normal) in triangles.iter_mut().zip(self.normals.iter()) {
+            tri.normal = normal.map(f32::from_bits);
+        }
+    }
+}
+
+pub struct NormalReader {
+    normals: Vec<[u32; 3]>,
+    shifts: Vec<u8>,
+    position: u64,
+}
+
+impl NormalReader {
+    pub fn new(triangles: &[stl::Triangle], bits: u8) -> NormalReader {
+        NormalReader {
+            normals: triangles
+                .iter()
+                .map(|t| t.normal.map(|c| c.to_bits()))
+                .collect(),
+            shifts: component_shifts(bits),
+            position: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.normals.len() as u64 * self.shifts.len() as u64
+    }
+
+    pub fn read_bits(&self) -> u64 {
+        self.position
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        if self.position >= self.capacity_bits() {
+            return 0;
+        }
+        let per_triangle = self.shifts.len() as u64;
+        let triangle = (self.position / per_triangle) as usize;
+        let slot = (self.position % per_triangle) as usize;
+        let component = slot / (self.shifts.len() / 3);
+        let shift = self.shifts[slot];
+        self.position += 1;
+
+        (self.normals[triangle][component] >> shift & 1) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normal_roundtrip() {
+        let mut triangles: Vec<stl::Triangle> = (0..10)
+            .map(|i| stl::Triangle {
+                normal: [0.0, 0.6, 0.8],
+                v1: [i as f32, 0.0, 0.0],
+                v2: [0.0, i as f32, 0.0],
+                v3: [0.0, 0.0, i as f32],
+                attr_byte_count: 0,
+            })
+            .collect();
+        let payload: Vec<u8> = (0..100).map(|i| ((i * 3) % 5 < 2) as u8).collect();
+
+        let mut writer = NormalWriter::new(&triangles, 4);
+        assert_eq!(writer.capacity_bits(), 120);
+        for bit in payload.iter() {
+            writer.push_bit(*bit);
+        }
+        writer.apply(&mut triangles);
+        // only the low 4 bits of each component may move
+        assert!((triangles[0].normal[1] - 0.6).abs() < 1e-5);
+
+        let mut reader = NormalReader::new(&triangles, 4);
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+
+        let mut writer = NormalWriter::new(&triangles, 32);
+        assert_eq!(writer.capacity_bits(), capacity_bits(&triangles, 32));
+        for bit in payload.iter() {
+            writer.push_bit(*bit);
+        }
+        writer.apply(&mut triangles);
+        let mut reader = NormalReader::new(&triangles, 32);
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+}
@@ -0,0 +1,207 @@
+use std::io;
+use std::io::Write;
+
+/*
+ *  Wavefront OBJ keeps its vertex positions in `v x y z` lines and builds faces out of indices
+ *  into them, so a vertex shared by many faces is written once. Faces are fanned into triangles
+ *  for the carriers, and positions are written back by index, which leaves every other line
+ *  (faces, texture coordinates, normals, groups, comments) exactly as it was.
+ *
+ *  A `v` line whose position didn't change is written back untouched too, only the moved ones
+ *  are reprinted, as the shortest decimal that reads back as the same f32.
+ */
+enum ObjLine {
+    Vertex {
+        position: usize,
+        rest: String,
+        ending: String,
+        original: String,
+    },
+    Other(String),
+}
+
+pub struct ObjFile {
+    lines: Vec<ObjLine>,
+    original_positions: Vec<[f32; 3]>,
+    positions: Vec<[f32; 3]>,
+    corners: Vec<[usize; 3]>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn face_index(token: &str, position_count: usize) -> io::Result<usize> {
+    // `7`, `7/2`, `7//3` or `7/2/3`, 1 based, or negative counting back from the last `v`
+    let index_text = token.split('/').next().unwrap_or("");
+    let index: i64 = index_text
+        .parse()
+        .map_err(|_| invalid(format!("`{}` is not a face vertex", token)))?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..0 => position_count as i64 + index,
+        0 => -1,
+    };
+    if resolved < 0 || resolved >= position_count as i64 {
+        return Err(invalid(format!("face vertex {} is out of range", index)));
+    }
+    Ok(resolved as usize)
+}
+
+pub fn read_obj(bytes: &[u8]) -> io::Result<ObjFile> {
+    let text = std::str::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+    let mut lines = Vec::<ObjLine>::new();
+    let mut positions = Vec::<[f32; 3]>::new();
+    let mut corners = Vec::<[usize; 3]>::new();
+
+    for raw in text.split_inclusive('\n') {
+        let content = raw.trim_end_matches(['\r', '\n']);
+        let ending = &raw[content.len()..];
+        let mut tokens = content.split_ascii_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut position = [0f32; 3];
+                for component in position.iter_mut() {
+                    let token = tokens.next().unwrap_or("");
+                    *component = token
+                        .parse::<f32>()
+                        .map_err(|_| invalid(format!("`{}` is not a number", token)))?;
+                }
+                lines.push(ObjLine::Vertex {
+                    position: positions.len(),
+                    rest: tokens.collect::<Vec<&str>>().join(" "),
+                    ending: ending.to_string(),
+                    original: raw.to_string(),
+                });
+                positions.push(position);
+            }
+            Some("f") => {
+                let face = tokens
+                    .map(|t| face_index(t, positions.len()))
+                    .collect::<io::Result<Vec<usize>>>()?;
+                for i in 1..face.len().saturating_sub(1) {
+                    corners.push([face[0], face[i], face[i + 1]]);
+                }
+                lines.push(ObjLine::Other(raw.to_string()));
+            }
+            _ => lines.push(ObjLine::Other(raw.to_string())),
+        }
+    }
+
+    Ok(ObjFile {
+        lines,
+        original_positions: positions.clone(),
+        positions,
+        corners,
+    })
+}
+
+impl ObjFile {
+    pub fn triangles(&self) -> Vec<stl::Triangle> {
+        self.corners
+            .iter()
+            .map(|c| stl::Triangle {
+                normal: [0.0, 0.0, 0.0],
+                v1: self.positions[c[0]],
+                v2: self.positions[c[1]],
+                v3: self.positions[c[2]],
+                attr_byte_count: 0,
+            })
+            .collect()
+    }
+
+    pub fn set_triangles(&mut self, triangles: &[stl::Triangle]) -> io::Result<()> {
+        // triangles must be the ones from triangles(), in the same order and the same rotation
+        if triangles.len() != self.corners.len() {
+            return Err(invalid(format!(
+                "{} triangles to write back into {} OBJ faces",
+                triangles.len(),
+                self.corners.len()
+            )));
+        }
+        for (corner, tri) in self.corners.iter().zip(triangles.iter()) {
+            self.positions[corner[0]] = tri.v1;
+            self.positions[corner[1]] = tri.v2;
+            self.positions[corner[2]] = tri.v3;
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for line in self.lines.iter() {
+            match line {
+                ObjLine::Vertex {
+                    position,
+                    rest,
+                    ending,
+                    original,
+                } => {
+                    let p = self.positions[*position];
+                    if p.map(f32::to_bits) == self.original_positions[*position].map(f32::to_bits) {
+                        writer.write_all(original.as_bytes())?;
+                        continue;
+                    }
+                    if p.iter().any(|c| !c.is_finite()) {
+                        return Err(invalid(format!(
+                            "vertex {:?} can't be written exactly in an OBJ",
+                            p
+                        )));
+                    }
+                    let separator = if rest.is_empty() { "" } else { " " };
+                    write!(
+                        writer,
+                        "v {} {} {}{}{}{}",
+                        p[0], p[1], p[2], separator, rest, ending
+                    )?;
+                }
+                ObjLine::Other(raw) => writer.write_all(raw.as_bytes())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_SIDE: &str = "# exported by hand\r\n\
+        mtllib side.mtl\r\n\
+        v 0.0 0.0 0.0\r\n\
+        v 1.0 0.0 0.0 0.5 0.5 0.5\r\n\
+        v 1.0 1.0 0.0\r\n\
+        v 0.0 1.0 0.0\r\n\
+        vt 0.25 0.75\r\n\
+        vn 0 0 1\r\n\
+        g side\r\n\
+        f 1/1/1 2/1/1 3/1/1 4/1/1\r\n\
+        f -4//1 -2//1 -1//1\r\n";
+
+    #[test]
+    fn test_obj_roundtrip() {
+        let mut obj = read_obj(CUBE_SIDE.as_bytes()).unwrap();
+        let mut triangles = obj.triangles();
+        // the quad fans into two triangles, plus the one triangle face
+        assert_eq!(triangles.len(), 3);
+        assert_eq!(triangles[2].v2, [1.0, 1.0, 0.0]);
+
+        let mut untouched = Vec::<u8>::new();
+        obj.write(&mut untouched).unwrap();
+        assert_eq!(untouched, CUBE_SIDE.as_bytes());
+
+        let nudged = f32::from_bits(1.0f32.to_bits() + 3);
+        triangles[0].v2 = [nudged, 0.0, 0.0];
+        triangles[1].v1 = [0.0, 0.0, 0.0];
+        obj.set_triangles(&triangles).unwrap();
+        let mut written = Vec::<u8>::new();
+        obj.write(&mut written).unwrap();
+        let text = String::from_utf8(written.clone()).unwrap();
+        assert!(text.contains("v 1.0000004 0 0 0.5 0.5 0.5\r\n"));
+        assert!(text.contains("v 0.0 0.0 0.0\r\n"));
+        assert!(text.contains("f 1/1/1 2/1/1 3/1/1 4/1/1\r\n"));
+
+        let reread = read_obj(&written).unwrap();
+        assert_eq!(reread.triangles()[0].v2[0].to_bits(), nudged.to_bits());
+        assert!(read_obj(b"v 0 0 0\nf 1 2 3\n").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::manip;
+use std::collections::HashMap;
+
+/*
+ *  Triangle order in a binary STL carries no meaning, so reordering the triangles can carry a
+ *  payload without moving a single vertex.
+ *
+ *  Every triangle gets a key from its geometry: its three vertices, with any bits the vertex
+ *  channel may write masked off, sorted so the key doesn't depend on which vertex comes first.
+ *  Sorting by key gives a canonical order both sides agree on. The payload is then read as a
+ *  sequence of digits in the factorial number system (a Lehmer code): with m triangles left to
+ *  place, the next digit picks which of them, by canonical rank, goes next.
+ *
+ *  Digits are taken as floor(log2(m)) whole bits, which wastes a fraction of a bit per triangle
+ *  against log2(n!) but keeps every digit independent of the others.
+ *
+ *  Triangles sharing a key can't be told apart once reordered, so they are left out of the
+ *  permutation and kept together at the end, in their original relative order.
+ */
+type TriangleKey = [u128; 3];
+
+fn triangle_key(tri: &stl::Triangle, mask_bits: u8) -> TriangleKey {
+    let mask = if mask_bits == 0 {
+        !0
+    } else {
+        manip::get_keep_mask(&mask_bits)
+    };
+    let mut key = [
+        manip::get_vertex_as_bits(tri, 0) & mask,
+        manip::get_vertex_as_bits(tri, 1) & mask,
+        manip::get_vertex_as_bits(tri, 2) & mask,
+    ];
+    key.sort_unstable();
+    key
+}
+
+fn split_by_key(triangles: &[stl::Triangle], mask_bits: u8) -> (Vec<TriangleKey>, Vec<bool>) {
+    // keys in file order, and whether each one is unique and so usable in the permutation
+    let keys: Vec<TriangleKey> = triangles
+        .iter()
+        .map(|t| triangle_key(t, mask_bits))
+        .collect();
+    let mut counts = HashMap::<TriangleKey, u32>::new();
+    for key in keys.iter() {
+        *counts.entry(*key).or_insert(0) += 1;
+    }
+    let usable = keys.iter().map(|k| counts[k] == 1).collect();
+    (keys, usable)
+}
+
+fn digit_bits(remaining: u64) -> u8 {
+    // floor(log2(remaining)), a single remaining triangle carries nothing
+    (63 - remaining.leading_zeros()) as u8
+}
+
+fn permutation_capacity(count: u64) -> u64 {
+    (1..=count).map(|m| digit_bits(m) as u64).sum()
+}
+
+fn sort_indices(triangles: &[stl::Triangle], mask_bits: u8) -> (Vec<usize>, usize) {
+    // the canonical order as file indices, and how many at the front take part in the permutation
+    let (keys, usable) = split_by_key(triangles, mask_bits);
+    let mut order: Vec<usize> = (0..triangles.len()).collect();
+    order.sort_by_key(|i| (!usable[*i], if usable[*i] { keys[*i] } else { [0; 3] }));
+    (order, usable.iter().filter(|u| **u).count())
+}
+
+fn sort_canonical(triangles: &[stl::Triangle], mask_bits: u8) -> (Vec<stl::Triangle>, usize) {
+    let (order, count) = sort_indices(triangles, mask_bits);
+    let sorted = order
+        .iter()
+        .map(|i| manip::copy_triangle(&triangles[*i]))
+        .collect();
+    (sorted, count)
+}
+
+pub fn canonical_indices(triangles: &[stl::Triangle], mask_bits: u8) -> Vec<usize> {
+    /*
+     *  The canonical order as file indices, with the shared-key triangles at the end.
+     *  The payload itself doesn't depend on the input order, but anything reading the mesh in
+     *  file order (like the vertex channel) has to start from this on both sides.
+     */
+    sort_indices(triangles, mask_bits).0
+}
+
+pub fn capacity_bits(triangles: &[stl::Triangle], mask_bits: u8) -> u64 {
+    let (_keys, usable) = split_by_key(triangles, mask_bits);
+    permutation_capacity(usable.iter().filter(|u| **u).count() as u64)
+}
+
+struct Fenwick {
+    tree: Vec<u32>,
+}
+
+impl Fenwick {
+    fn full(len: usize) -> Fenwick {
+        // every slot starts out present
+        let mut tree = vec![0u32; len + 1];
+        for i in 1..=len {
+            tree[i] += 1;
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= len {
+                tree[parent] += tree[i];
+            }
+        }
+        Fenwick { tree }
+    }
+
+    fn remove(&mut self, index: usize) {
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn count_before(&self, index: usize) -> u64 {
+        let mut sum: u64 = 0;
+        let mut i = index;
+        while i > 0 {
+            sum += self.tree[i] as u64;
+            i -= i & i.wrapping_neg();
+        }
+        sum
+    }
+
+    fn find_nth(&self, n: u64) -> usize {
+        // index of the present slot with n present slots ahead of it
+        let mut pos = 0;
+        let mut remaining = n;
+        let mut step = (self.tree.len()).next_power_of_two();
+        while step > 0 {
+            let next = pos + step;
+            if next < self.tree.len() && (self.tree[next] as u64) <= remaining {
+                pos = next;
+                remaining -= self.tree[next] as u64;
+            }
+            step >>= 1;
+        }
+        pos
+    }
+}
+
+pub struct PermutationWriter {
+    mask_bits: u8,
+    capacity: u64,
+    bits: Vec<u8>,
+}
+
+impl PermutationWriter {
+    pub fn new(triangles: &[stl::Triangle], mask_bits: u8) -> PermutationWriter {
+        PermutationWriter {
+            mask_bits,
+            capacity: capacity_bits(triangles, mask_bits),
+            bits: Vec::<u8>::new(),
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn pushed_bits(&self) -> u64 {
+        self.bits.len() as u64
+    }
+
+    pub fn push_bit(&mut self, bit: u8) {
+        if (self.bits.len() as u64) < self.capacity {
+            self.bits.push(bit);
+        }
+    }
+
+    pub fn finish(self, triangles: &[stl::Triangle]) -> Vec<stl::Triangle> {
+        let (sorted, count) = sort_canonical(triangles, self.mask_bits);
+
+        let mut remaining = Fenwick::full(count);
+        let mut bits = self.bits.into_iter();
+        let mut output = Vec::<stl::Triangle>::with_capacity(sorted.len());
+        for placed in 0..count {
+            //bits are taken LSB first, anything past the end of the payload is zero
+            let mut digit: u64 = 0;
+            for b in 0..digit_bits((count - placed) as u64) {
+                digit |= (bits.next().unwrap_or(0) as u64) << b;
+            }
+            let index = remaining.find_nth(digit);
+            remaining.remove(index);
+            output.push(manip::copy_triangle(&sorted[index]));
+        }
+        output.extend(sorted[count..].iter().map(manip::copy_triangle));
+        output
+    }
+}
+
+pub struct PermutationReader {
+    ranks: Vec<usize>,
+    remaining: Fenwick,
+    position: usize,
+    digit: u64,
+    digit_bits_left: u8,
+    read: u64,
+}
+
+impl PermutationReader {
+    pub fn new(triangles: &[stl::Triangle], mask_bits: u8) -> PermutationReader {
+        let (keys, usable) = split_by_key(triangles, mask_bits);
+        let mut sorted: Vec<TriangleKey> = keys
+            .iter()
+            .zip(usable.iter())
+            .filter(|(_, u)| **u)
+            .map(|(k, _)| *k)
+            .collect();
+        sorted.sort_unstable();
+        let ranks: Vec<usize> = keys
+            .iter()
+            .zip(usable.iter())
+            .filter(|(_, u)| **u)
+            .map(|(k, _)| sorted.binary_search(k).unwrap())
+            .collect();
+
+        PermutationReader {
+            remaining: Fenwick::full(ranks.len()),
+            ranks,
+            position: 0,
+            digit: 0,
+            digit_bits_left: 0,
+            read: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        permutation_capacity(self.ranks.len() as u64)
+    }
+
+    pub fn read_bits(&self) -> u64 {
+        self.read
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        self.read += 1;
+        while self.digit_bits_left == 0 {
+            if self.position >= self.ranks.len() {
+                return 0;
+            }
+            let rank = self.ranks[self.position];
+            self.digit_bits_left = digit_bits((self.ranks.len() - self.position) as u64);
+            self.digit = self.remaining.count_before(rank);
+            self.remaining.remove(rank);
+            self.position += 1;
+        }
+        let bit = (self.digit & 1) as u8;
+        self.digit >>= 1;
+        self.digit_bits_left -= 1;
+        bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(i: u32) -> stl::Triangle {
+        let f = |n: u32| (n as f32) * 1.5 + 0.25;
+        stl::Triangle {
+            normal: [0.0, 0.0, 1.0],
+            v1: [f(i), f(i + 1), f(i + 2)],
+            v2: [f(i + 3), f(i), f(i + 7)],
+            v3: [f(i + 1), f(i + 5), f(i)],
+            attr_byte_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(permutation_capacity(1), 0);
+        // 1 + 1 + 2 + 2 + 2 + 2 + 3
+        assert_eq!(permutation_capacity(8), 13);
+    }
+
+    #[test]
+    fn test_permutation_roundtrip() {
+        let mut triangles: Vec<stl::Triangle> = (0..300).rev().map(triangle).collect();
+        // a repeated triangle can't carry anything and must survive untouched
+        triangles.push(triangle(5));
+        let payload: Vec<u8> = (0..500).map(|i| ((i * 7) % 3 == 0) as u8).collect();
+
+        let mut writer = PermutationWriter::new(&triangles, 0);
+        assert_eq!(writer.capacity_bits(), permutation_capacity(299));
+        for bit in payload.iter() {
+            writer.push_bit(*bit);
+        }
+        let permuted = writer.finish(&triangles);
+        assert_eq!(permuted.len(), triangles.len());
+
+        let mut reader = PermutationReader::new(&permuted, 0);
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+}
@@ -0,0 +1,534 @@
+use std::io;
+use std::io::Write;
+
+use crate::manip::{self, VertexSet};
+use crate::mesh;
+
+/*
+ *  PLY keeps an indexed vertex list like OBJ, but describes its own layout in a header: any
+ *  number of elements, each with scalar or list properties, stored as text or as little or big
+ *  endian binary. Scanners and point cloud tools often write the positions as `double`, which
+ *  has 52 mantissa bits per coordinate where an STL only has 23.
+ *
+ *  The vertex channel works on the positions directly, in the order they appear, so a point
+ *  cloud without any faces carries data too. Positions stay in their own precision. Only the
+ *  bytes of coordinates that changed are rewritten, the header and every other property are
+ *  written back exactly as they were read.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlyEncoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8),
+            "uchar" | "uint8" => Some(ScalarType::UInt8),
+            "short" | "int16" => Some(ScalarType::Int16),
+            "ushort" | "uint16" => Some(ScalarType::UInt16),
+            "int" | "int32" => Some(ScalarType::Int32),
+            "uint" | "uint32" => Some(ScalarType::UInt32),
+            "float" | "float32" => Some(ScalarType::Float32),
+            "double" | "float64" => Some(ScalarType::Float64),
+            _ => None,
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(ScalarType),
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+pub struct PlyFile {
+    header: Vec<u8>,
+    body: Vec<u8>,
+    encoding: PlyEncoding,
+    double: bool,
+    // where x, y and z of every vertex are in the body, as start and end offsets
+    spans: Vec<[(usize, usize); 3]>,
+    original_positions: Vec<[f64; 3]>,
+    positions: Vec<[f64; 3]>,
+    corners: Vec<[usize; 3]>,
+    // the distinct positions as read, and which of them each vertex is
+    uniq_positions: VertexSet,
+    vertex_index: Vec<u32>,
+}
+
+fn index_positions(positions: &[[f64; 3]], double: bool) -> (VertexSet, Vec<u32>) {
+    match double {
+        true => {
+            let bits: Vec<[u64; 3]> = positions
+                .iter()
+                .map(manip::get_double_position_as_bits)
+                .collect();
+            let (uniq, index) = mesh::index_positions(&bits);
+            (VertexSet::Double(uniq), index)
+        }
+        false => {
+            let bits: Vec<[u32; 3]> = positions
+                .iter()
+                .map(|p| p.map(|c| (c as f32).to_bits()))
+                .collect();
+            let (uniq, index) = mesh::index_positions(&bits);
+            (VertexSet::Single(uniq), index)
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct BodyCursor<'a> {
+    body: &'a [u8],
+    position: usize,
+    encoding: PlyEncoding,
+}
+
+impl BodyCursor<'_> {
+    fn ascii_token(&mut self) -> io::Result<(&str, (usize, usize))> {
+        while self.position < self.body.len() && self.body[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.position < self.body.len() && !self.body[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(invalid("PLY body ends early".to_string()));
+        }
+        let token = std::str::from_utf8(&self.body[start..self.position])
+            .map_err(|e| invalid(e.to_string()))?;
+        Ok((token, (start, self.position)))
+    }
+
+    fn binary_bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let bytes = self
+            .body
+            .get(self.position..self.position + N)
+            .ok_or_else(|| invalid("PLY body ends early".to_string()))?;
+        self.position += N;
+        let mut array: [u8; N] = bytes.try_into().unwrap();
+        if self.encoding == PlyEncoding::BinaryBigEndian {
+            array.reverse();
+        }
+        Ok(array)
+    }
+
+    fn scalar(&mut self, scalar: ScalarType) -> io::Result<(f64, (usize, usize))> {
+        // floats are read in their own precision first, an f32 widens to f64 exactly
+        if self.encoding == PlyEncoding::Ascii {
+            let (token, span) = self.ascii_token()?;
+            let not_a_number = || invalid(format!("`{}` is not a number", token));
+            let value = match scalar {
+                ScalarType::Float32 => token.parse::<f32>().map_err(|_| not_a_number())? as f64,
+                ScalarType::Float64 => token.parse::<f64>().map_err(|_| not_a_number())?,
+                _ => token.parse::<i64>().map_err(|_| not_a_number())? as f64,
+            };
+            return Ok((value, span));
+        }
+        let start = self.position;
+        let value = match scalar {
+            ScalarType::Int8 => i8::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::UInt8 => u8::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::Int16 => i16::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::Int32 => i32::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::Float32 => f32::from_le_bytes(self.binary_bytes()?) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(self.binary_bytes()?),
+        };
+        Ok((value, (start, self.position)))
+    }
+}
+
+fn parse_header(text: &str) -> io::Result<(PlyEncoding, Vec<Element>)> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim_end) != Some("ply") {
+        return Err(invalid("not a PLY file".to_string()));
+    }
+    let mut encoding = None;
+    let mut elements = Vec::<Element>::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _version] => {
+                encoding = Some(match *name {
+                    "ascii" => PlyEncoding::Ascii,
+                    "binary_little_endian" => PlyEncoding::BinaryLittleEndian,
+                    "binary_big_endian" => PlyEncoding::BinaryBigEndian,
+                    _ => return Err(invalid(format!("unknown PLY format `{}`", name))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid(format!("`{}` is not an element count", count)))?,
+                properties: Vec::<Property>::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let kind = match (ScalarType::from_name(count), ScalarType::from_name(item)) {
+                    (Some(count), Some(item)) => PropertyKind::List(count, item),
+                    _ => return Err(invalid(format!("unknown PLY list `{}`", line))),
+                };
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY property before any element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            ["property", scalar, name] => {
+                let kind = ScalarType::from_name(scalar)
+                    .map(PropertyKind::Scalar)
+                    .ok_or_else(|| invalid(format!("unknown PLY type `{}`", scalar)))?;
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid("PLY property before any element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            ["end_header"] => break,
+            // comment, obj_info and blank lines
+            _ => {}
+        }
+    }
+    let encoding = encoding.ok_or_else(|| invalid("PLY header has no format".to_string()))?;
+    Ok((encoding, elements))
+}
+
+fn position_type(element: &Element) -> io::Result<ScalarType> {
+    // x, y and z have to be there and share one float type
+    let mut types = Vec::<ScalarType>::new();
+    for axis in ["x", "y", "z"] {
+        match element.properties.iter().find(|p| p.name == axis) {
+            Some(Property {
+                kind: PropertyKind::Scalar(scalar),
+                ..
+            }) => types.push(*scalar),
+            _ => return Err(invalid(format!("PLY vertices have no scalar `{}`", axis))),
+        }
+    }
+    match types[..] {
+        [a, b, c] if a == b && b == c && (a == ScalarType::Float32 || a == ScalarType::Float64) => {
+            Ok(a)
+        }
+        _ => Err(invalid(
+            "PLY vertex positions must all be float or all be double".to_string(),
+        )),
+    }
+}
+
+pub fn read_ply(bytes: &[u8]) -> io::Result<PlyFile> {
+    const END_HEADER: &[u8] = b"end_header";
+    let marker = bytes
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or_else(|| invalid("PLY header has no end_header".to_string()))?;
+    let header_end = bytes[marker..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map(|p| marker + p + 1)
+        .unwrap_or(bytes.len());
+    let header_text =
+        std::str::from_utf8(&bytes[..header_end]).map_err(|e| invalid(e.to_string()))?;
+    let (encoding, elements) = parse_header(header_text)?;
+
+    let body = &bytes[header_end..];
+    let mut cursor = BodyCursor {
+        body,
+        position: 0,
+        encoding,
+    };
+    let mut double = false;
+    let mut spans = Vec::<[(usize, usize); 3]>::new();
+    let mut positions = Vec::<[f64; 3]>::new();
+    let mut corners = Vec::<[usize; 3]>::new();
+
+    for element in elements.iter() {
+        let is_vertex = element.name == "vertex";
+        if is_vertex {
+            double = position_type(element)? == ScalarType::Float64;
+        }
+        for _ in 0..element.count {
+            let mut position = [0f64; 3];
+            let mut span = [(0usize, 0usize); 3];
+            for property in element.properties.iter() {
+                match property.kind {
+                    PropertyKind::Scalar(scalar) => {
+                        let (value, value_span) = cursor.scalar(scalar)?;
+                        let axis = match property.name.as_str() {
+                            "x" => 0,
+                            "y" => 1,
+                            "z" => 2,
+                            _ => continue,
+                        };
+                        position[axis] = value;
+                        span[axis] = value_span;
+                    }
+                    PropertyKind::List(count, item) => {
+                        let (length, _) = cursor.scalar(count)?;
+                        let mut face = Vec::<usize>::new();
+                        for _ in 0..length as usize {
+                            face.push(cursor.scalar(item)?.0 as usize);
+                        }
+                        let is_face = element.name == "face"
+                            && (property.name == "vertex_indices"
+                                || property.name == "vertex_index");
+                        if is_face {
+                            for i in 1..face.len().saturating_sub(1) {
+                                corners.push([face[0], face[i], face[i + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                positions.push(position);
+                spans.push(span);
+            }
+        }
+    }
+    if let Some(corner) = corners.iter().flatten().find(|c| **c >= positions.len()) {
+        return Err(invalid(format!("face vertex {} is out of range", corner)));
+    }
+
+    let (uniq_positions, vertex_index) = index_positions(&positions, double);
+    Ok(PlyFile {
+        header: bytes[..header_end].to_vec(),
+        body: body.to_vec(),
+        encoding,
+        double,
+        spans,
+        original_positions: positions.clone(),
+        positions,
+        corners,
+        uniq_positions,
+        vertex_index,
+    })
+}
+
+impl PlyFile {
+    pub fn is_double(&self) -> bool {
+        self.double
+    }
+
+    pub fn vertices(&self) -> VertexSet {
+        // unique positions as read, in file order, faces or not
+        match &self.uniq_positions {
+            VertexSet::Single(uniq) => VertexSet::Single(uniq.clone()),
+            VertexSet::Double(uniq) => VertexSet::Double(uniq.clone()),
+        }
+    }
+
+    pub fn triangles(&self) -> Vec<stl::Triangle> {
+        let single = |i: usize| self.positions[i].map(|c| c as f32);
+        self.corners
+            .iter()
+            .map(|c| stl::Triangle {
+                normal: [0.0, 0.0, 0.0],
+                v1: single(c[0]),
+                v2: single(c[1]),
+                v3: single(c[2]),
+                attr_byte_count: 0,
+            })
+            .collect()
+    }
+
+    pub fn apply_vertices(&mut self, written: &VertexSet) -> io::Result<()> {
+        // written replaces vertices(), index for index, so it can be applied again
+        if written.len() != self.uniq_positions.len() {
+            return Err(invalid(format!(
+                "{} vertices to write back into {} PLY vertices",
+                written.len(),
+                self.uniq_positions.len()
+            )));
+        }
+        for (position, index) in self.positions.iter_mut().zip(self.vertex_index.iter()) {
+            let index = *index as usize;
+            *position = match (written, self.double) {
+                (VertexSet::Double(written), true) => {
+                    manip::get_double_position_from_bits(&written[index])
+                }
+                (VertexSet::Single(written), false) => {
+                    written[index].map(|c| f32::from_bits(c) as f64)
+                }
+                _ => {
+                    return Err(invalid(
+                        "vertex precision doesn't match the PLY positions".to_string(),
+                    ));
+                }
+            };
+        }
+        Ok(())
+    }
+
+    fn coordinate_bytes(&self, value: f64) -> io::Result<Vec<u8>> {
+        if !value.is_finite() {
+            return Err(invalid(format!(
+                "{} can't be written exactly in a PLY",
+                value
+            )));
+        }
+        // Display prints the shortest text that reads back as the same value
+        Ok(match (self.encoding, self.double) {
+            (PlyEncoding::Ascii, true) => format!("{}", value).into_bytes(),
+            (PlyEncoding::Ascii, false) => format!("{}", value as f32).into_bytes(),
+            (PlyEncoding::BinaryLittleEndian, true) => value.to_le_bytes().to_vec(),
+            (PlyEncoding::BinaryLittleEndian, false) => (value as f32).to_le_bytes().to_vec(),
+            (PlyEncoding::BinaryBigEndian, true) => value.to_be_bytes().to_vec(),
+            (PlyEncoding::BinaryBigEndian, false) => (value as f32).to_be_bytes().to_vec(),
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        // x, y and z don't have to be in that order within a vertex, so sort what changed
+        let mut changes = Vec::<((usize, usize), f64)>::new();
+        for (i, position) in self.positions.iter().enumerate() {
+            for (axis, value) in position.iter().enumerate() {
+                if value.to_bits() != self.original_positions[i][axis].to_bits() {
+                    changes.push((self.spans[i][axis], *value));
+                }
+            }
+        }
+        changes.sort_by_key(|((start, _), _)| *start);
+
+        writer.write_all(&self.header)?;
+        let mut written: usize = 0;
+        for ((start, end), value) in changes {
+            writer.write_all(&self.body[written..start])?;
+            writer.write_all(&self.coordinate_bytes(value)?)?;
+            written = end;
+        }
+        writer.write_all(&self.body[written..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: &str = "ply\n\
+        format ascii 1.0\n\
+        comment made by hand\n\
+        element vertex 5\n\
+        property double x\n\
+        property double y\n\
+        property double z\n\
+        property uchar red\n\
+        element face 1\n\
+        property list uchar int vertex_indices\n\
+        end_header\n\
+        0.0 0.0 0.0 255\n\
+        1.0 0.0 0.0 128\n\
+        1.0 1.0 0.0 0\n\
+        0.0 1.0 0.0 7\n\
+        0.5 0.5 2.0e1 9\n\
+        4 0 1 2 3\n";
+
+    fn binary_square(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "big" } else { "little" };
+        let mut bytes = format!(
+            "ply\nformat binary_{}_endian 1.0\nelement vertex 3\nproperty float x\n\
+             property float y\nproperty float z\nelement face 1\n\
+             property list uchar uint vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+        for v in [[0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.5]] {
+            for c in v {
+                bytes.extend(if big_endian {
+                    c.to_be_bytes()
+                } else {
+                    c.to_le_bytes()
+                });
+            }
+        }
+        bytes.push(3);
+        for i in 0u32..3 {
+            bytes.extend(if big_endian {
+                i.to_be_bytes()
+            } else {
+                i.to_le_bytes()
+            });
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_ply_roundtrip() {
+        let mut ply = read_ply(SQUARE.as_bytes()).unwrap();
+        assert!(ply.is_double());
+        assert_eq!(ply.triangles().len(), 2);
+        // the fifth point isn't part of any face but still counts
+        assert_eq!(ply.vertices().len(), 5);
+
+        let mut untouched = Vec::<u8>::new();
+        ply.write(&mut untouched).unwrap();
+        assert_eq!(untouched, SQUARE.as_bytes());
+
+        let moved = [0.5, f64::from_bits(0.5f64.to_bits() + 1), 20.0];
+        let mut vertices = ply.vertices();
+        if let VertexSet::Double(vertices) = &mut vertices {
+            vertices[4] = manip::get_double_position_as_bits(&moved);
+        }
+        ply.apply_vertices(&vertices).unwrap();
+        let mut written = Vec::<u8>::new();
+        ply.write(&mut written).unwrap();
+        let text = String::from_utf8(written.clone()).unwrap();
+        assert!(text.contains("\n0.5 0.5000000000000001 2.0e1 9\n"));
+        assert_eq!(read_ply(&written).unwrap().positions[4], moved);
+
+        for big_endian in [false, true] {
+            let bytes = binary_square(big_endian);
+            let mut ply = read_ply(&bytes).unwrap();
+            assert!(!ply.is_double());
+            assert_eq!(ply.triangles()[0].v3, [1.0, 1.0, 0.5]);
+            let nudged = f32::from_bits(0.5f32.to_bits() + 3);
+            let mut vertices = ply.vertices();
+            if let VertexSet::Single(vertices) = &mut vertices {
+                vertices[2][2] = nudged.to_bits();
+            }
+            ply.apply_vertices(&vertices).unwrap();
+            let mut written = Vec::<u8>::new();
+            ply.write(&mut written).unwrap();
+            assert_eq!(written.len(), bytes.len());
+            assert_eq!(read_ply(&written).unwrap().triangles()[0].v3[2], nudged);
+        }
+        assert!(
+            read_ply(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty int x\nend_header\n1\n")
+                .is_err()
+        );
+    }
+}
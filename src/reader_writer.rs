@@ -1,13 +1,289 @@
+use crate::ecc::{self, EccError};
+use crate::error::{Result as StegaResult, StegaError};
 use bitstream_io::{BitQueue, LittleEndian};
-use std::io::{Bytes as FileBytes, Read};
-use std::iter::Cycle;
-use std::str::Bytes;
+use crc32fast::Hasher;
+use flate2::Compression;
+use flate2::read::{DeflateDecoder, DeflateEncoder};
+use flate2::write::DeflateEncoder as DeflateWriter;
+use std::fmt;
+use std::io::{self, BufReader, Bytes as FileBytes, Read, Write};
+use std::vec::IntoIter;
 
 pub const HEADER_BYTES: u64 = 8;
 
+/*
+ *  Framed payload layout:
+ *   - 4 bytes magic, FRAME_MAGIC
+ *   - 1 byte format version
+ *   - 1 byte flags (FLAG_*)
+ *   - 8 bytes payload length, little-endian
+ *   - if FLAG_ECC: 1 byte of Reed-Solomon parity bytes per block
+ *   - payload
+ *   - 4 bytes CRC32 of everything above, little-endian
+ *  With FLAG_ECC set, payload and CRC are stored Reed-Solomon encoded and interleaved (see ecc),
+ *  the header ahead of them is not.
+ */
+pub const FRAME_MAGIC: [u8; 4] = *b"SSTL";
+pub const FRAME_VERSION: u8 = 1;
+pub const FRAME_HEADER_BYTES: u64 = 14;
+pub const FRAME_TRAILER_BYTES: u64 = 4;
+pub const FRAME_OVERHEAD: u64 = FRAME_HEADER_BYTES + FRAME_TRAILER_BYTES;
+pub const FRAME_ECC_BYTES: u64 = 1;
+
+pub const FLAG_COMPRESSED: u8 = 0b0000_0001;
+pub const FLAG_ENCRYPTED: u8 = 0b0000_0010;
+pub const FLAG_ECC: u8 = 0b0000_0100;
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    NoPayload,
+    UnsupportedVersion(u8),
+    ExceedsCapacity {
+        payload_bytes: u64,
+        available_bytes: u64,
+    },
+    ChecksumMismatch {
+        expected: u32,
+        found: u32,
+    },
+    Ecc(EccError),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::NoPayload => write!(f, "no payload found"),
+            FrameError::UnsupportedVersion(v) => {
+                write!(f, "payload frame version {} is not supported", v)
+            }
+            FrameError::ExceedsCapacity {
+                payload_bytes,
+                available_bytes,
+            } => write!(
+                f,
+                "no payload found: frame claims {} bytes but only {} fit at this bit depth",
+                payload_bytes, available_bytes
+            ),
+            FrameError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "payload checksum mismatch: expected {:08x}, found {:08x}",
+                expected, found
+            ),
+            FrameError::Ecc(e) => write!(f, "payload damaged beyond repair: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+#[derive(Debug, PartialEq)]
+pub struct FrameHeader {
+    version: u8,
+    flags: u8,
+    payload_bytes: u64,
+    ecc_parity: u8,
+}
+
+pub struct DecodedFrame {
+    pub header: FrameHeader,
+    pub payload: Vec<u8>,
+    pub corrected: usize,
+}
+
+impl FrameHeader {
+    pub fn new(flags: u8, payload_bytes: u64) -> FrameHeader {
+        FrameHeader {
+            version: FRAME_VERSION,
+            flags,
+            payload_bytes,
+            ecc_parity: 0,
+        }
+    }
+
+    pub fn with_ecc(mut self, parity: u8) -> FrameHeader {
+        self.flags |= FLAG_ECC;
+        self.ecc_parity = parity;
+        self
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<FrameHeader, FrameError> {
+        if bytes.len() < FRAME_HEADER_BYTES as usize || bytes[0..4] != FRAME_MAGIC {
+            return Err(FrameError::NoPayload);
+        }
+        if bytes[4] != FRAME_VERSION {
+            return Err(FrameError::UnsupportedVersion(bytes[4]));
+        }
+        let mut length = [0u8; 8];
+        length.copy_from_slice(&bytes[6..14]);
+        Ok(FrameHeader {
+            version: bytes[4],
+            flags: bytes[5],
+            payload_bytes: u64::from_le_bytes(length),
+            ecc_parity: 0,
+        })
+    }
+
+    pub fn extension_bytes(&self) -> u64 {
+        if self.has_flag(FLAG_ECC) {
+            return FRAME_ECC_BYTES;
+        }
+        0
+    }
+
+    pub fn parse_extension(&mut self, bytes: &[u8]) -> Result<(), FrameError> {
+        if self.has_flag(FLAG_ECC) {
+            let parity = bytes[0];
+            if !(ecc::MIN_PARITY..=ecc::MAX_PARITY).contains(&parity) {
+                return Err(FrameError::NoPayload);
+            }
+            self.ecc_parity = parity;
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::<u8>::with_capacity(FRAME_HEADER_BYTES as usize);
+        bytes.extend_from_slice(&FRAME_MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.payload_bytes.to_le_bytes());
+        if self.has_flag(FLAG_ECC) {
+            bytes.push(self.ecc_parity);
+        }
+        bytes
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag == flag
+    }
+
+    pub fn payload_bytes(&self) -> u64 {
+        self.payload_bytes
+    }
+
+    pub fn ecc_parity(&self) -> Option<u8> {
+        if self.has_flag(FLAG_ECC) {
+            return Some(self.ecc_parity);
+        }
+        None
+    }
+
+    pub fn body_bytes(&self) -> u64 {
+        // payload and CRC trailer as stored, after any error correction coding
+        let body = self.payload_bytes.saturating_add(FRAME_TRAILER_BYTES);
+        match self.ecc_parity() {
+            Some(parity) => ecc::encoded_len(body, parity),
+            None => body,
+        }
+    }
+
+    pub fn stored_bytes(&self) -> u64 {
+        FRAME_HEADER_BYTES + self.extension_bytes() + self.body_bytes()
+    }
+
+    pub fn check_capacity(&self, available_bytes: u64) -> Result<(), FrameError> {
+        if self.payload_bytes > available_bytes || self.stored_bytes() > available_bytes {
+            return Err(FrameError::ExceedsCapacity {
+                payload_bytes: self.payload_bytes,
+                available_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn verify(&self, payload: &[u8], trailer: &[u8]) -> Result<(), FrameError> {
+        let mut hasher = Hasher::new();
+        hasher.update(&self.to_bytes());
+        hasher.update(payload);
+        let found = hasher.finalize();
+        let mut expected = [0u8; 4];
+        expected.copy_from_slice(&trailer[..FRAME_TRAILER_BYTES as usize]);
+        let expected = u32::from_le_bytes(expected);
+        if expected != found {
+            return Err(FrameError::ChecksumMismatch { expected, found });
+        }
+        Ok(())
+    }
+
+    pub fn open_body(&self, body: &[u8]) -> Result<(Vec<u8>, usize), FrameError> {
+        let (mut payload, corrected) = match self.ecc_parity() {
+            Some(parity) => ecc::decode(body, self.payload_bytes + FRAME_TRAILER_BYTES, parity)
+                .map_err(FrameError::Ecc)?,
+            None => (body.to_vec(), 0),
+        };
+        let trailer = payload.split_off(self.payload_bytes as usize);
+        self.verify(&payload, &trailer)?;
+        Ok((payload, corrected))
+    }
+}
+
+pub fn read_frame<F>(mut read_bytes: F, available_bytes: u64) -> Result<DecodedFrame, FrameError>
+where
+    F: FnMut(u64) -> Vec<u8>,
+{
+    /*
+     *  Pulls a whole frame through read_bytes, which must return exactly the number of bytes
+     *  asked for, rejecting it as early as possible if it does not look like one of ours.
+     */
+    let mut header = FrameHeader::parse(&read_bytes(FRAME_HEADER_BYTES))?;
+    let extension_bytes = header.extension_bytes();
+    if extension_bytes > 0 {
+        header.parse_extension(&read_bytes(extension_bytes))?;
+    }
+    header.check_capacity(available_bytes)?;
+    let body = read_bytes(header.body_bytes());
+    let (payload, corrected) = header.open_body(&body)?;
+    Ok(DecodedFrame {
+        header,
+        payload,
+        corrected,
+    })
+}
+
+pub fn ensure_capacity(needed_bytes: u64, available_bytes: u64) -> StegaResult<()> {
+    // what an encode checks before writing anything, needed_bytes including any header
+    if needed_bytes > available_bytes {
+        return Err(StegaError::InsufficientCapacity {
+            needed_bytes,
+            available_bytes,
+        });
+    }
+    Ok(())
+}
+
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    // raw DEFLATE, the frame flags already say it is compressed so no zlib wrapper is needed
+    let mut compressed = Vec::<u8>::new();
+    DeflateEncoder::new(data, Compression::best()).read_to_end(&mut compressed)?;
+    Ok(compressed)
+}
+
+pub fn compress_repeated(data: &[u8], times: u64, limit_bytes: u64) -> io::Result<Vec<u8>> {
+    // data times over without holding the repeats, given up once the output passes limit_bytes
+    let mut encoder = DeflateWriter::new(Vec::<u8>::new(), Compression::best());
+    for _ in 0..times {
+        encoder.write_all(data)?;
+        if encoder.get_ref().len() as u64 > limit_bytes {
+            break;
+        }
+    }
+    encoder.finish()
+}
+
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::<u8>::new();
+    DeflateDecoder::new(data).read_to_end(&mut output)?;
+    Ok(output)
+}
+
 pub trait BitFeed {
     fn done(&self) -> bool;
-    fn get_bit(&mut self) -> u8;
+    // fails when the payload can't be read
+    fn get_bit(&mut self) -> StegaResult<u8>;
 }
 
 struct BytesIntoBits {
@@ -26,6 +302,14 @@ impl BytesIntoBits {
             bit_queue: BitQueue::from_value(header_val as u128, 64),
         }
     }
+    fn new_headerless() -> BytesIntoBits {
+        BytesIntoBits {
+            bytes_pushed: 0,
+            bits_in_byte_consumed: 0,
+            bytes_consumed: 0,
+            bit_queue: BitQueue::new(),
+        }
+    }
     fn can_accept_byte(&self) -> bool {
         self.bit_queue.max_len() - self.bit_queue.len() >= 8
     }
@@ -48,48 +332,15 @@ impl BytesIntoBits {
     }
 }
 
-pub struct StringEncoder<'a> {
-    iter: Cycle<Bytes<'a>>,
-    total_bytes: u64,
-    feeder: BytesIntoBits,
-}
-
-impl<'a> StringEncoder<'a> {
-    pub fn new(content: &'a String, times: &u64) -> StringEncoder<'a> {
-        let encoded_bytes = content.len() as u64 * times;
-        StringEncoder {
-            iter: content.bytes().cycle(),
-            total_bytes: HEADER_BYTES + encoded_bytes,
-            feeder: BytesIntoBits::new(encoded_bytes),
-        }
-    }
-}
-
-impl<'a> BitFeed for StringEncoder<'a> {
-    fn done(&self) -> bool {
-        self.feeder.bytes_consumed() >= self.total_bytes
-    }
-    fn get_bit(&mut self) -> u8 {
-        //keep pushing data into the queue, excess will be ignored
-        if self.feeder.can_accept_byte() {
-            self.feeder.accept_byte(self.iter.next().unwrap());
-        }
-        self.feeder.get_bit()
-    }
-}
-
 pub trait ByteFeed {
     fn push_bit(&mut self, bit: u8);
     fn bytes_available(&self) -> u32;
     fn get_byte(&mut self) -> u8;
-    fn get_header_bytes(&mut self) -> u64;
-    fn header_was_read(&self) -> bool;
 }
 
 struct BitsIntoBytes {
     bytes_pushed: u64,
     bits_in_byte_pushed: u8,
-    bytes_consumed: u64,
     bit_queue: BitQueue<LittleEndian, u128>,
 }
 impl BitsIntoBytes {
@@ -97,7 +348,6 @@ impl BitsIntoBytes {
         BitsIntoBytes {
             bytes_pushed: 0,
             bits_in_byte_pushed: 0,
-            bytes_consumed: 0,
             bit_queue: BitQueue::new(),
         }
     }
@@ -112,59 +362,16 @@ impl BitsIntoBytes {
             self.bytes_pushed += 1;
         }
     }
-    fn bytes_consumed(&self) -> u64 {
-        self.bytes_consumed
-    }
     fn bytes_available(&self) -> u32 {
         self.bit_queue.len() / 8
     }
     fn get_byte(&mut self) -> u8 {
-        let res = self.bit_queue.pop(8) as u8;
-        self.bytes_consumed += 1;
-
-        res
-    }
-    fn get_header_bytes(&mut self) -> u64 {
-        let res = self.bit_queue.pop(64) as u64;
-        self.bytes_consumed += 8;
-
-        res
-    }
-}
-
-pub struct StringDecoder {
-    feeder: BitsIntoBytes,
-}
-
-impl StringDecoder {
-    pub fn new() -> StringDecoder {
-        StringDecoder {
-            feeder: BitsIntoBytes::new(),
-        }
-    }
-}
-
-impl ByteFeed for StringDecoder {
-    fn push_bit(&mut self, bit: u8) {
-        assert!(self.feeder.can_accept_bit(), "bit feed full");
-        self.feeder.accept_bit(bit);
-    }
-    fn bytes_available(&self) -> u32 {
-        self.feeder.bytes_available()
-    }
-    fn get_byte(&mut self) -> u8 {
-        self.feeder.get_byte()
-    }
-    fn get_header_bytes(&mut self) -> u64 {
-        self.feeder.get_header_bytes()
-    }
-    fn header_was_read(&self) -> bool {
-        self.feeder.bytes_consumed() >= 8
+        self.bit_queue.pop(8) as u8
     }
 }
 
 pub struct BinaryEncoder<R: Read> {
-    iter: FileBytes<R>,
+    iter: FileBytes<BufReader<R>>,
     total_bytes: u64,
     feeder: BytesIntoBits,
 }
@@ -172,18 +379,27 @@ pub struct BinaryEncoder<R: Read> {
 impl<R: Read> BinaryEncoder<R> {
     pub fn new(file: R, filesize: u64) -> BinaryEncoder<R> {
         BinaryEncoder {
-            iter: file.bytes(),
+            iter: BufReader::new(file).bytes(),
             total_bytes: HEADER_BYTES + filesize,
             feeder: BytesIntoBits::new(filesize),
         }
     }
+
+    pub fn headerless(file: R, filesize: u64) -> BinaryEncoder<R> {
+        //no length header, the decoding side has to know the length some other way
+        BinaryEncoder {
+            iter: BufReader::new(file).bytes(),
+            total_bytes: filesize,
+            feeder: BytesIntoBits::new_headerless(),
+        }
+    }
 }
 
 impl<R: Read> BitFeed for BinaryEncoder<R> {
     fn done(&self) -> bool {
         self.feeder.bytes_consumed() >= self.total_bytes
     }
-    fn get_bit(&mut self) -> u8 {
+    fn get_bit(&mut self) -> StegaResult<u8> {
         //keep pushing data into the queue, excess will be ignored
         if self.feeder.can_accept_byte() {
             match self.iter.next() {
@@ -191,14 +407,14 @@ impl<R: Read> BitFeed for BinaryEncoder<R> {
                     self.feeder.accept_byte(byte);
                 }
                 Some(Err(e)) => {
-                    panic!("Error reading byte: {}", e);
+                    return Err(e.into());
                 }
                 None => {
                     // EOF
                 }
             }
         }
-        self.feeder.get_bit()
+        Ok(self.feeder.get_bit())
     }
 }
 
@@ -214,6 +430,12 @@ impl BinaryDecoder {
     }
 }
 
+impl Default for BinaryDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ByteFeed for BinaryDecoder {
     fn push_bit(&mut self, bit: u8) {
         assert!(self.feeder.can_accept_bit(), "bit feed full");
@@ -225,10 +447,186 @@ impl ByteFeed for BinaryDecoder {
     fn get_byte(&mut self) -> u8 {
         self.feeder.get_byte()
     }
-    fn get_header_bytes(&mut self) -> u64 {
-        self.feeder.get_header_bytes()
+}
+
+pub struct FramedEncoder<R: Read> {
+    queued: IntoIter<u8>,
+    iter: FileBytes<BufReader<R>>,
+    payload_remaining: u64,
+    hasher: Hasher,
+    trailer: Option<IntoIter<u8>>,
+    total_bytes: u64,
+    feeder: BytesIntoBits,
+}
+
+impl<R: Read> FramedEncoder<R> {
+    pub fn new(file: R, filesize: u64, flags: u8) -> FramedEncoder<R> {
+        let header = FrameHeader::new(flags, filesize).to_bytes();
+        let mut hasher = Hasher::new();
+        hasher.update(&header);
+        FramedEncoder {
+            queued: header.into_iter(),
+            iter: BufReader::new(file).bytes(),
+            payload_remaining: filesize,
+            hasher,
+            trailer: None,
+            total_bytes: FRAME_OVERHEAD + filesize,
+            feeder: BytesIntoBits::new_headerless(),
+        }
+    }
+
+    pub fn with_ecc(mut file: R, filesize: u64, flags: u8, parity: u8) -> StegaResult<Self> {
+        //the whole body has to be in hand to be encoded and interleaved, so nothing streams here
+        let header = FrameHeader::new(flags, filesize).with_ecc(parity);
+        let mut body = Vec::<u8>::with_capacity(filesize as usize);
+        file.by_ref().take(filesize).read_to_end(&mut body)?;
+        if (body.len() as u64) < filesize {
+            return Err(StegaError::PayloadTruncated);
+        }
+
+        let mut hasher = Hasher::new();
+        let mut frame = header.to_bytes();
+        hasher.update(&frame);
+        hasher.update(&body);
+        body.extend_from_slice(&hasher.clone().finalize().to_le_bytes());
+        frame.extend(ecc::encode(&body, parity));
+
+        Ok(FramedEncoder {
+            total_bytes: frame.len() as u64,
+            queued: frame.into_iter(),
+            iter: BufReader::new(file).bytes(),
+            payload_remaining: 0,
+            hasher,
+            trailer: Some(Vec::new().into_iter()),
+            feeder: BytesIntoBits::new_headerless(),
+        })
+    }
+
+    fn next_byte(&mut self) -> StegaResult<Option<u8>> {
+        if let Some(byte) = self.queued.next() {
+            return Ok(Some(byte));
+        }
+        if self.payload_remaining > 0 {
+            self.payload_remaining -= 1;
+            return match self.iter.next() {
+                Some(Ok(byte)) => {
+                    self.hasher.update(&[byte]);
+                    Ok(Some(byte))
+                }
+                Some(Err(e)) => Err(e.into()),
+                None => Err(StegaError::PayloadTruncated),
+            };
+        }
+        let hasher = &self.hasher;
+        Ok(self
+            .trailer
+            .get_or_insert_with(|| hasher.clone().finalize().to_le_bytes().to_vec().into_iter())
+            .next())
+    }
+}
+
+impl<R: Read> BitFeed for FramedEncoder<R> {
+    fn done(&self) -> bool {
+        self.feeder.bytes_consumed() >= self.total_bytes
+    }
+    fn get_bit(&mut self) -> StegaResult<u8> {
+        if self.feeder.can_accept_byte()
+            && let Some(byte) = self.next_byte()?
+        {
+            self.feeder.accept_byte(byte);
+        }
+        Ok(self.feeder.get_bit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn roundtrip(encoder: &mut impl BitFeed) -> Vec<u8> {
+        let mut decoder = BinaryDecoder::new();
+        let mut output = Vec::<u8>::new();
+        while !encoder.done() {
+            decoder.push_bit(encoder.get_bit().unwrap());
+            if decoder.bytes_available() > 0 {
+                output.push(decoder.get_byte());
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn test_frame_header() {
+        let header = FrameHeader::new(FLAG_ENCRYPTED, 1234);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len() as u64, FRAME_HEADER_BYTES);
+        assert_eq!(FrameHeader::parse(&bytes), Ok(header));
+        assert_eq!(FrameHeader::parse(&[0u8; 14]), Err(FrameError::NoPayload));
+
+        let header = FrameHeader::parse(&bytes).unwrap();
+        assert!(header.has_flag(FLAG_ENCRYPTED));
+        assert!(!header.has_flag(FLAG_COMPRESSED));
+        assert!(header.check_capacity(1234 + FRAME_OVERHEAD).is_ok());
+        assert!(header.check_capacity(1233 + FRAME_OVERHEAD).is_err());
+        assert!(ensure_capacity(header.stored_bytes(), 1234 + FRAME_OVERHEAD).is_ok());
+        assert!(matches!(
+            ensure_capacity(header.stored_bytes(), 1233 + FRAME_OVERHEAD),
+            Err(StegaError::InsufficientCapacity {
+                needed_bytes: 1252,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let text = "Created by a great guy.".repeat(10);
+        let compressed = compress(text.as_bytes()).unwrap();
+        assert!(compressed.len() < text.len() / 4);
+        assert_eq!(decompress(&compressed).unwrap(), text.as_bytes());
+        assert!(decompress(&[0xff; 8]).is_err());
     }
-    fn header_was_read(&self) -> bool {
-        self.feeder.bytes_consumed() >= 8
+
+    #[test]
+    fn test_framed_encoder() {
+        let payload = b"a benchy full of hamlet".to_vec();
+        let mut encoder = FramedEncoder::new(Cursor::new(payload.clone()), 23, 0);
+        let output = roundtrip(&mut encoder);
+        assert_eq!(output.len() as u64, FRAME_OVERHEAD + 23);
+
+        let (header, rest) = output.split_at(FRAME_HEADER_BYTES as usize);
+        let (body, trailer) = rest.split_at(23);
+        let header = FrameHeader::parse(header).unwrap();
+        assert_eq!(header.payload_bytes(), 23);
+        assert_eq!(body, &payload[..]);
+        assert_eq!(header.verify(body, trailer), Ok(()));
+        assert!(header.verify(b"a benchy full of omelet", trailer).is_err());
+
+        // a payload shorter than it claims to be is an error, not a panic
+        let mut short = FramedEncoder::new(Cursor::new(payload[..5].to_vec()), 23, 0);
+        let failed = (0..FRAME_OVERHEAD * 8 + 23 * 8).find_map(|_| short.get_bit().err());
+        assert!(matches!(failed, Some(StegaError::PayloadTruncated)));
+        assert!(matches!(
+            FramedEncoder::with_ecc(Cursor::new(payload[..5].to_vec()), 23, 0, 8),
+            Err(StegaError::PayloadTruncated)
+        ));
+    }
+
+    #[test]
+    fn test_framed_encoder_with_ecc() {
+        let payload = b"a benchy full of hamlet".to_vec();
+        let mut encoder = FramedEncoder::with_ecc(Cursor::new(payload.clone()), 23, 0, 8).unwrap();
+        let mut output = roundtrip(&mut encoder);
+        assert_eq!(output.len() as u64, FRAME_HEADER_BYTES + 1 + 23 + 4 + 8);
+        output[20] ^= 0xff;
+        output[30] ^= 0x0f;
+
+        let mut stored = output.into_iter();
+        let read_bytes = |n: u64| stored.by_ref().take(n as usize).collect::<Vec<u8>>();
+        let frame = read_frame(read_bytes, 100).unwrap();
+        assert_eq!(frame.header.ecc_parity(), Some(8));
+        assert_eq!(frame.payload, payload);
+        assert_eq!(frame.corrected, 2);
     }
 }
@@ -0,0 +1,217 @@
+use crate::manip;
+
+/*
+ *  Cycling a triangle's vertices (v1,v2,v3 -> v2,v3,v1) keeps its winding and geometry exactly,
+ *  so which vertex comes first is a free choice of three: a trit per triangle.
+ *
+ *  The canonical start is the lexicographically smallest vertex, with any bits the vertex
+ *  channel may write masked off. The trit is how many places the stored triangle is rotated
+ *  away from that start. A triangle whose smallest vertex is shared (a degenerate one) has no
+ *  well defined start and is skipped.
+ *
+ *  Trits are grouped 12 at a time to carry 19 bits, since 3^12 = 531441 just covers
+ *  2^19 = 524288. That is 1.58 bits per triangle against the ideal log2(3).
+ */
+const GROUP_TRITS: usize = 12;
+const GROUP_BITS: u8 = 19;
+
+fn smallest_vertex(tri: &stl::Triangle, mask_bits: u8) -> Option<u8> {
+    let mask = if mask_bits == 0 {
+        !0
+    } else {
+        manip::get_keep_mask(&mask_bits)
+    };
+    let vertices: Vec<u128> = (0..3)
+        .map(|i| manip::get_vertex_as_bits(tri, i) & mask)
+        .collect();
+    let smallest = *vertices.iter().min().unwrap();
+    if vertices.iter().filter(|v| **v == smallest).count() > 1 {
+        return None;
+    }
+    vertices
+        .iter()
+        .position(|v| *v == smallest)
+        .map(|i| i as u8)
+}
+
+pub fn capacity_bits(triangles: &[stl::Triangle], mask_bits: u8) -> u64 {
+    let usable = triangles
+        .iter()
+        .filter(|t| smallest_vertex(t, mask_bits).is_some())
+        .count();
+    (usable / GROUP_TRITS) as u64 * GROUP_BITS as u64
+}
+
+pub fn canonical_starts(triangles: &[stl::Triangle], mask_bits: u8) -> Vec<u8> {
+    // the smallest vertex of every usable triangle, 0 for the rest
+    triangles
+        .iter()
+        .map(|t| smallest_vertex(t, mask_bits).unwrap_or(0))
+        .collect()
+}
+
+pub struct RotationWriter {
+    mask_bits: u8,
+    capacity: u64,
+    bits: Vec<u8>,
+}
+
+impl RotationWriter {
+    pub fn new(triangles: &[stl::Triangle], mask_bits: u8) -> RotationWriter {
+        RotationWriter {
+            mask_bits,
+            capacity: capacity_bits(triangles, mask_bits),
+            bits: Vec::<u8>::new(),
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn pushed_bits(&self) -> u64 {
+        self.bits.len() as u64
+    }
+
+    pub fn push_bit(&mut self, bit: u8) {
+        if (self.bits.len() as u64) < self.capacity {
+            self.bits.push(bit);
+        }
+    }
+
+    pub fn rotations(&self, triangles: &[stl::Triangle]) -> Vec<u8> {
+        /*
+         *  The rotation to apply to each triangle as it stands, for IndexedMesh::write_triangles.
+         *  Bits are taken LSB first into each 19 bit group, anything past the end of the payload
+         *  is zero, so unused triangles end up at their canonical start.
+         */
+        let mut trits = Vec::<u8>::new();
+        for group in self.bits.chunks(GROUP_BITS as usize) {
+            let mut value: u32 = 0;
+            for (b, bit) in group.iter().enumerate() {
+                value |= (*bit as u32) << b;
+            }
+            for _ in 0..GROUP_TRITS {
+                trits.push((value % 3) as u8);
+                value /= 3;
+            }
+        }
+
+        let mut trits = trits.into_iter();
+        triangles
+            .iter()
+            .map(|t| match smallest_vertex(t, self.mask_bits) {
+                Some(start) => (start + trits.next().unwrap_or(0)) % 3,
+                None => 0,
+            })
+            .collect()
+    }
+}
+
+pub struct RotationReader {
+    trits: Vec<u8>,
+    bits_read: u64,
+    group: usize,
+    value: u32,
+    bits_left: u8,
+}
+
+impl RotationReader {
+    pub fn new(triangles: &[stl::Triangle], mask_bits: u8) -> RotationReader {
+        // the rotation away from the canonical start is where the smallest vertex now sits
+        let trits: Vec<u8> = triangles
+            .iter()
+            .filter_map(|t| smallest_vertex(t, mask_bits))
+            .map(|position| (3 - position) % 3)
+            .collect();
+        RotationReader {
+            trits,
+            bits_read: 0,
+            group: 0,
+            value: 0,
+            bits_left: 0,
+        }
+    }
+
+    pub fn capacity_bits(&self) -> u64 {
+        (self.trits.len() / GROUP_TRITS) as u64 * GROUP_BITS as u64
+    }
+
+    pub fn read_bits(&self) -> u64 {
+        self.bits_read
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        self.bits_read += 1;
+        if self.bits_left == 0 {
+            let start = self.group * GROUP_TRITS;
+            if start + GROUP_TRITS > self.trits.len() {
+                return 0;
+            }
+            self.value = self.trits[start..start + GROUP_TRITS]
+                .iter()
+                .rev()
+                .fold(0, |value, trit| value * 3 + *trit as u32);
+            self.group += 1;
+            self.bits_left = GROUP_BITS;
+        }
+        let bit = (self.value & 1) as u8;
+        self.value >>= 1;
+        self.bits_left -= 1;
+        bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotate_triangles(triangles: &[stl::Triangle], rotations: &[u8]) -> Vec<stl::Triangle> {
+        triangles
+            .iter()
+            .zip(rotations.iter())
+            .map(|(t, r)| {
+                let v = [t.v1, t.v2, t.v3];
+                stl::Triangle {
+                    normal: t.normal,
+                    v1: v[*r as usize % 3],
+                    v2: v[(*r as usize + 1) % 3],
+                    v3: v[(*r as usize + 2) % 3],
+                    attr_byte_count: t.attr_byte_count,
+                }
+            })
+            .collect()
+    }
+
+    fn triangle(i: u32) -> stl::Triangle {
+        let f = |n: u32| (n as f32) * 1.5 + 0.25;
+        stl::Triangle {
+            normal: [0.0, 0.0, 1.0],
+            v1: [f(i + 3), f(i), f(i + 7)],
+            v2: [f(i), f(i + 1), f(i + 2)],
+            v3: [f(i + 1), f(i + 5), f(i)],
+            attr_byte_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_rotation_roundtrip() {
+        let mut triangles: Vec<stl::Triangle> = (0..50).map(triangle).collect();
+        // a degenerate triangle has no canonical start and must be skipped
+        triangles[10].v3 = triangles[10].v2;
+        let payload: Vec<u8> = (0..60).map(|i| ((i * 5) % 3 == 0) as u8).collect();
+
+        let mut writer = RotationWriter::new(&triangles, 0);
+        assert_eq!(writer.capacity_bits(), 4 * 19);
+        for bit in payload.iter() {
+            writer.push_bit(*bit);
+        }
+        let rotations = writer.rotations(&triangles);
+        let rotated = rotate_triangles(&triangles, &rotations);
+        assert_eq!(rotated[10].v1, triangles[10].v1);
+
+        let mut reader = RotationReader::new(&rotated, 0);
+        let output: Vec<u8> = payload.iter().map(|_| reader.next_bit()).collect();
+        assert_eq!(output, payload);
+    }
+}
@@ -1,37 +1,521 @@
+use std::fmt;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::{BufWriter, Write};
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
 
-use crate::manip;
+use crate::carrier::Embedded;
+use crate::digits::DigitWriter;
+use crate::error::{Result, StegaError};
+use crate::header;
+use crate::manip::VertexSet;
+use crate::mesh::IndexedMesh;
+use crate::obj::{self, ObjFile};
+use crate::ply::{self, PlyFile};
+use crate::stream::{self, StreamedStl};
+use crate::threemf::{self, ThreeMfFile};
 
-pub fn load_stl(file: &mut File) -> stl::BinaryStlFile {
-    let stl = stl::read_stl(file).unwrap();
-    stl
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StlFormat {
+    Binary,
+    Ascii,
 }
 
-pub fn load_and_report(file_path: String) -> (stl::BinaryStlFile, Vec<u128>) {
-    println!("File: {}", file_path);
-    let mut file = OpenOptions::new().read(true).open(file_path).unwrap();
-    let mut uniq_vertices = Vec::<u128>::new();
-    let stl = load_stl(&mut file);
+impl StlFormat {
+    pub const NAMES: [&'static str; 2] = ["binary", "ascii"];
 
-    manip::get_uniq_vertices_as_ordered_bits(&stl.triangles, &mut uniq_vertices);
-    let num_vert = uniq_vertices.len();
-    println!("Tris: {}", stl.triangles.len());
-    println!("Vertices: {}", num_vert);
+    pub fn from_name(name: &str) -> Option<StlFormat> {
+        match name {
+            "binary" => Some(StlFormat::Binary),
+            "ascii" => Some(StlFormat::Ascii),
+            _ => None,
+        }
+    }
 
-    (stl, uniq_vertices)
+    pub fn detect(bytes: &[u8]) -> StlFormat {
+        /*
+         *  Some binary exporters start their header with "solid" too, so a file whose size
+         *  matches its own triangle count is binary no matter what the header says.
+         */
+        if bytes.len() >= 84 {
+            let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as u64;
+            if 84 + 50 * count == bytes.len() as u64 {
+                return StlFormat::Binary;
+            }
+        }
+        let start = bytes.iter().position(|b| !b.is_ascii_whitespace());
+        match start {
+            Some(start) if bytes[start..].starts_with(b"solid") => StlFormat::Ascii,
+            _ => StlFormat::Binary,
+        }
+    }
 }
 
-pub fn write_stl(filename: String, stl: stl::BinaryStlFile) {
-    let file = File::create(filename).unwrap();
+/*
+ *  The carriers all work on STL triangles. Other mesh formats are turned into triangles on
+ *  load, and the source is kept so the changed triangles can be written back into it. Either
+ *  way the triangles end up in an IndexedMesh, the STL the loaders return only has its header.
+ *  A PLY is the exception, its vertex positions are carried as they are (see ply.rs).
+ *  So is a streamed STL, which never holds its triangles at all (see stream.rs).
+ */
+pub enum MeshSource {
+    Stl,
+    Obj(ObjFile),
+    Ply(PlyFile),
+    ThreeMf(ThreeMfFile),
+    Streamed(StreamedStl),
+}
+
+impl MeshSource {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MeshSource::Stl | MeshSource::Streamed(_) => "stl",
+            MeshSource::Obj(_) => "obj",
+            MeshSource::Ply(_) => "ply",
+            MeshSource::ThreeMf(_) => "3mf",
+        }
+    }
+
+    pub fn is_stl(&self) -> bool {
+        matches!(self, MeshSource::Stl)
+    }
+
+    pub fn component_bits(&self) -> u8 {
+        // the widest bit depth the vertex channel can use
+        match self {
+            MeshSource::Ply(ply) if ply.is_double() => 64,
+            _ => 32,
+        }
+    }
+
+    pub fn vertices(&self, mesh: &IndexedMesh) -> VertexSet {
+        match self {
+            MeshSource::Ply(ply) => ply.vertices(),
+            MeshSource::Streamed(streamed) => VertexSet::Single(streamed.vertices.clone()),
+            _ => VertexSet::Single(mesh.vertex_bits()),
+        }
+    }
+}
+
+/*
+ *  What the loaders found, for the tools to print. The library itself prints nothing.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshStats {
+    pub file_path: String,
+    pub triangles: u64,
+    pub vertices: u64,
+    pub double_precision: bool,
+}
+
+impl MeshStats {
+    pub fn new(file_path: &str, mesh: &IndexedMesh, source: &MeshSource) -> MeshStats {
+        // a PLY counts the vertices it carries, which can be more than the triangles use
+        let (triangles, vertices) = match source {
+            MeshSource::Streamed(streamed) => {
+                (streamed.num_triangles as u64, streamed.vertices.len())
+            }
+            MeshSource::Ply(ply) => (mesh.triangles.len() as u64, ply.vertices().len()),
+            _ => (mesh.triangles.len() as u64, mesh.vertices.len()),
+        };
+        MeshStats {
+            file_path: file_path.to_string(),
+            triangles,
+            vertices: vertices as u64,
+            double_precision: source.component_bits() == 64,
+        }
+    }
+}
+
+impl fmt::Display for MeshStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "File: {}\nTris: {}\nVertices: {}",
+            self.file_path, self.triangles, self.vertices
+        )?;
+        if self.double_precision {
+            write!(f, "\nPrecision: double")?;
+        }
+        Ok(())
+    }
+}
+
+fn stl_from_triangles(triangles: Vec<stl::Triangle>) -> stl::BinaryStlFile {
+    stl::BinaryStlFile {
+        header: stl::BinaryStlHeader {
+            header: [b' '; header::STL_HEADER_BYTES],
+            num_triangles: triangles.len() as u32,
+        },
+        triangles,
+    }
+}
+
+fn has_extension(file_path: &str, extension: &str) -> bool {
+    Path::new(file_path)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+pub fn read_stl(bytes: &[u8]) -> io::Result<stl::BinaryStlFile> {
+    match StlFormat::detect(bytes) {
+        StlFormat::Binary => stl::read_stl(&mut io::Cursor::new(bytes)),
+        StlFormat::Ascii => read_ascii_stl(bytes),
+    }
+}
+
+pub fn load_stl(file: &mut File) -> io::Result<stl::BinaryStlFile> {
+    let mut bytes = Vec::<u8>::new();
+    file.read_to_end(&mut bytes)?;
+    read_stl(&bytes)
+}
+
+fn mesh_error(file_path: &str) -> impl FnOnce(io::Error) -> StegaError {
+    // which file it was, the io::Error alone rarely says
+    let path = file_path.to_string();
+    move |source| StegaError::Mesh { path, source }
+}
+
+fn index_triangles(stl: &mut stl::BinaryStlFile) -> IndexedMesh {
+    // the mesh keeps everything the triangles had, so only the header is left in the STL
+    let mesh = IndexedMesh::from_triangles(&stl.triangles);
+    stl.triangles = Vec::new();
+    mesh
+}
+
+pub fn load_indexed_stl(file_path: &str) -> Result<(stl::BinaryStlFile, IndexedMesh, MeshStats)> {
+    let mut stl = OpenOptions::new()
+        .read(true)
+        .open(file_path)
+        .and_then(|mut file| load_stl(&mut file))
+        .map_err(mesh_error(file_path))?;
+
+    let mesh = index_triangles(&mut stl);
+    let stats = MeshStats::new(file_path, &mesh, &MeshSource::Stl);
+    Ok((stl, mesh, stats))
+}
+
+pub fn load_mesh(file_path: &str) -> Result<(stl::BinaryStlFile, MeshSource)> {
+    load_mesh_source(file_path).map_err(mesh_error(file_path))
+}
+
+fn load_mesh_source(file_path: &str) -> io::Result<(stl::BinaryStlFile, MeshSource)> {
+    let bytes = std::fs::read(file_path)?;
+    if has_extension(file_path, "obj") {
+        let obj = obj::read_obj(&bytes)?;
+        return Ok((stl_from_triangles(obj.triangles()), MeshSource::Obj(obj)));
+    }
+    if has_extension(file_path, "ply") {
+        let ply = ply::read_ply(&bytes)?;
+        return Ok((stl_from_triangles(ply.triangles()), MeshSource::Ply(ply)));
+    }
+    if has_extension(file_path, "3mf") {
+        let package = threemf::read_3mf(bytes)?;
+        return Ok((
+            stl_from_triangles(package.triangles()),
+            MeshSource::ThreeMf(package),
+        ));
+    }
+    Ok((read_stl(&bytes)?, MeshSource::Stl))
+}
+
+pub fn load_indexed_mesh(
+    file_path: &str,
+) -> Result<(stl::BinaryStlFile, IndexedMesh, MeshSource, MeshStats)> {
+    let (mut stl, source) = load_mesh(file_path)?;
+    let mesh = index_triangles(&mut stl);
+    let stats = MeshStats::new(file_path, &mesh, &source);
+    Ok((stl, mesh, source, stats))
+}
+
+pub fn stream_mesh(
+    file_path: &str,
+) -> Result<(stl::BinaryStlFile, IndexedMesh, MeshSource, MeshStats)> {
+    // only the unique vertices are loaded, the triangles and mesh stay empty
+    let streamed = stream::scan_stl(file_path).map_err(mesh_error(file_path))?;
+    let empty = IndexedMesh::default();
+    let source = MeshSource::Streamed(streamed);
+    let stats = MeshStats::new(file_path, &empty, &source);
+    Ok((stl_from_triangles(Vec::new()), empty, source, stats))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn expect_word<'a>(tokens: &mut impl Iterator<Item = &'a str>, word: &str) -> io::Result<()> {
+    match tokens.next() {
+        Some(token) if token == word => Ok(()),
+        other => Err(invalid(format!("expected `{}`, found {:?}", word, other))),
+    }
+}
+
+fn read_vector<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> io::Result<[f32; 3]> {
+    let mut vector = [0f32; 3];
+    for component in vector.iter_mut() {
+        let token = tokens.next().unwrap_or("");
+        *component = token
+            .parse::<f32>()
+            .map_err(|_| invalid(format!("`{}` is not a number", token)))?;
+    }
+    Ok(vector)
+}
+
+pub fn read_ascii_stl(bytes: &[u8]) -> io::Result<stl::BinaryStlFile> {
+    /*
+     *  solid <name>
+     *    facet normal nx ny nz
+     *      outer loop
+     *        vertex x y z   (three times)
+     *      endloop
+     *    endfacet
+     *  endsolid <name>
+     *
+     *  The name goes into the header the way a binary banner would, ASCII has no attribute bytes.
+     */
+    let text = std::str::from_utf8(bytes).map_err(|e| invalid(e.to_string()))?;
+    let text = text.trim_start();
+    let (first_line, body) = text.split_once('\n').unwrap_or((text, ""));
+    let name = first_line
+        .trim_end()
+        .strip_prefix("solid")
+        .unwrap_or("")
+        .trim();
+
+    let mut tokens = body.split_ascii_whitespace();
+    let mut triangles = Vec::<stl::Triangle>::new();
+    loop {
+        match tokens.next() {
+            Some("facet") => {}
+            Some("endsolid") | None => break,
+            Some(other) => return Err(invalid(format!("expected `facet`, found `{}`", other))),
+        }
+        expect_word(&mut tokens, "normal")?;
+        let normal = read_vector(&mut tokens)?;
+        expect_word(&mut tokens, "outer")?;
+        expect_word(&mut tokens, "loop")?;
+        let mut vertices = [[0f32; 3]; 3];
+        for vertex in vertices.iter_mut() {
+            expect_word(&mut tokens, "vertex")?;
+            *vertex = read_vector(&mut tokens)?;
+        }
+        expect_word(&mut tokens, "endloop")?;
+        expect_word(&mut tokens, "endfacet")?;
+        triangles.push(stl::Triangle {
+            normal,
+            v1: vertices[0],
+            v2: vertices[1],
+            v3: vertices[2],
+            attr_byte_count: 0,
+        });
+    }
+
+    let mut header = [b' '; header::STL_HEADER_BYTES];
+    let name_bytes = &name.as_bytes()[..name.len().min(header::STL_HEADER_BYTES)];
+    header[..name_bytes.len()].copy_from_slice(name_bytes);
+    Ok(stl::BinaryStlFile {
+        header: stl::BinaryStlHeader {
+            header,
+            num_triangles: triangles.len() as u32,
+        },
+        triangles,
+    })
+}
+
+fn exact_number(value: f32) -> io::Result<String> {
+    /*
+     *  `{:e}` prints the shortest decimal that parses back to the exact same f32, so every bit
+     *  that was embedded survives the trip through text. NaN and infinity have no such decimal,
+     *  their bits would be lost, so they are refused instead.
+     */
+    match value.is_finite() {
+        true => Ok(format!("{:e}", value)),
+        false => Err(invalid(format!(
+            "{} can't be written exactly in an ASCII STL",
+            value
+        ))),
+    }
+}
+
+pub fn write_ascii_stl<W: Write>(writer: &mut W, stl: &stl::BinaryStlFile) -> io::Result<()> {
+    write_ascii_stl_with(writer, stl, exact_number)
+}
+
+pub fn write_ascii_stl_with<W, F>(
+    writer: &mut W,
+    stl: &stl::BinaryStlFile,
+    mut number: F,
+) -> io::Result<()>
+where
+    W: Write,
+    F: FnMut(f32) -> io::Result<String>,
+{
+    // number is called for every coordinate in file order: the normal, then each vertex
+    let mut vector = |v: &[f32; 3]| -> io::Result<String> {
+        Ok(format!(
+            "{} {} {}",
+            number(v[0])?,
+            number(v[1])?,
+            number(v[2])?
+        ))
+    };
+
+    let name = header::banner(&stl.header.header);
+    writeln!(writer, "solid {}", name)?;
+    for tri in stl.triangles.iter() {
+        writeln!(writer, "  facet normal {}", vector(&tri.normal)?)?;
+        writeln!(writer, "    outer loop")?;
+        for v in [&tri.v1, &tri.v2, &tri.v3] {
+            writeln!(writer, "      vertex {}", vector(v)?)?;
+        }
+        writeln!(writer, "    endloop")?;
+        writeln!(writer, "  endfacet")?;
+    }
+    writeln!(writer, "endsolid {}", name)?;
+    Ok(())
+}
+
+pub fn write_stl(filename: String, stl: stl::BinaryStlFile, format: StlFormat) -> Result<()> {
+    write_embedded_stl(filename, stl, None, format)
+}
+
+pub fn write_embedded_stl(
+    filename: String,
+    stl: stl::BinaryStlFile,
+    digits: Option<DigitWriter>,
+    format: StlFormat,
+) -> Result<()> {
+    let file = File::create(filename)?;
     let mut writer = BufWriter::new(file);
-    stl::write_stl(&mut writer, &stl).expect("Error writing output file");
+    match (format, digits) {
+        (StlFormat::Binary, None) => stl::write_stl(&mut writer, &stl)?,
+        (StlFormat::Binary, Some(_)) => {
+            return Err(invalid("digits can only be embedded in an ASCII STL".to_string()).into());
+        }
+        (StlFormat::Ascii, None) => write_ascii_stl(&mut writer, &stl)?,
+        (StlFormat::Ascii, Some(mut digits)) => {
+            write_ascii_stl_with(&mut writer, &stl, |v| digits.format(v))?
+        }
+    }
+    Ok(writer.flush()?)
+}
+
+pub fn write_mesh(
+    filename: String,
+    embedded: Embedded,
+    format: StlFormat,
+    source: &mut MeshSource,
+) -> Result<()> {
+    // anything but an STL is written back as itself, format only picks between the two kinds of STL
+    if !source.is_stl() && embedded.digits.is_some() {
+        return Err(invalid("digits can only be embedded in an ASCII STL".to_string()).into());
+    }
+    match source {
+        MeshSource::Stl => write_embedded_stl(filename, embedded.stl, embedded.digits, format),
+        MeshSource::Obj(obj) => {
+            obj.set_triangles(&embedded.stl.triangles)?;
+            let mut writer = BufWriter::new(File::create(filename)?);
+            obj.write(&mut writer)?;
+            Ok(writer.flush()?)
+        }
+        MeshSource::ThreeMf(package) => {
+            package.set_triangles(&embedded.stl.triangles)?;
+            let mut writer = BufWriter::new(File::create(filename)?);
+            package.write(&mut writer)?;
+            Ok(writer.flush()?)
+        }
+        MeshSource::Ply(ply) => {
+            if let Some(positions) = &embedded.positions {
+                ply.apply_vertices(positions)?;
+            }
+            let mut writer = BufWriter::new(File::create(filename)?);
+            ply.write(&mut writer)?;
+            Ok(writer.flush()?)
+        }
+        MeshSource::Streamed(streamed) => {
+            if format == StlFormat::Ascii {
+                return Err(
+                    invalid("a streamed STL can only be written as binary".to_string()).into(),
+                );
+            }
+            let mut file = File::create(filename)?;
+            Ok(streamed.write(&mut file, embedded.positions.as_ref())?)
+        }
+    }
 }
 
-pub fn write_binary_file(filename: String, bytes: Vec<u8>) {
-    let file = File::create(filename).unwrap();
+pub fn write_binary_file(filename: String, bytes: Vec<u8>) -> Result<()> {
+    let file = File::create(filename)?;
     let mut writer = BufWriter::new(file);
-    writer.write_all(&bytes).expect("Error writing output file");
-    writer.flush().expect("Error flushing output file");
+    writer.write_all(&bytes)?;
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_roundtrip() {
+        let triangles: Vec<stl::Triangle> = (0..5)
+            .map(|i| stl::Triangle {
+                normal: [0.0, -0.0, 1.0],
+                v1: [0.1 * i as f32, f32::from_bits(0x3f80_0001), 1e-40],
+                v2: [-3.5e12, i as f32, f32::MIN_POSITIVE],
+                v3: [f32::MAX, 123.456, -7.0],
+                attr_byte_count: 0,
+            })
+            .collect();
+        let stl = stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: header::with_banner("roundtrip part").unwrap(),
+                num_triangles: triangles.len() as u32,
+            },
+            triangles,
+        };
+
+        let mut text = Vec::<u8>::new();
+        write_ascii_stl(&mut text, &stl).unwrap();
+        assert_eq!(StlFormat::detect(&text), StlFormat::Ascii);
+        let read = read_stl(&text).unwrap();
+        assert_eq!(header::banner(&read.header.header), "roundtrip part");
+        assert_eq!(read.triangles.len(), stl.triangles.len());
+        for (a, b) in read.triangles.iter().zip(stl.triangles.iter()) {
+            for (va, vb) in [
+                (a.normal, b.normal),
+                (a.v1, b.v1),
+                (a.v2, b.v2),
+                (a.v3, b.v3),
+            ] {
+                assert_eq!(va.map(f32::to_bits), vb.map(f32::to_bits));
+            }
+        }
+
+        // a binary file that happens to start with "solid" is still binary
+        let mut binary = Vec::<u8>::new();
+        let mut solid_header = stl;
+        solid_header.header.header = header::with_banner("solid by some exporter").unwrap();
+        stl::write_stl(&mut binary, &solid_header).unwrap();
+        assert_eq!(StlFormat::detect(&binary), StlFormat::Binary);
+
+        let mut nan = solid_header;
+        nan.triangles[0].normal[0] = f32::NAN;
+        assert!(write_ascii_stl(&mut Vec::<u8>::new(), &nan).is_err());
+    }
+
+    #[test]
+    fn test_mesh_stats() {
+        let triangle = |z: f32| stl::Triangle {
+            normal: [0.0, 0.0, 1.0],
+            v1: [0.0, 0.0, z],
+            v2: [1.0, 0.0, z],
+            v3: [0.0, 1.0, 0.0],
+            attr_byte_count: 0,
+        };
+        let mesh = IndexedMesh::from_triangles(&[triangle(0.0), triangle(1.0)]);
+        let stats = MeshStats::new("part.stl", &mesh, &MeshSource::Stl);
+        assert_eq!((stats.triangles, stats.vertices), (2, 5));
+        assert_eq!(stats.to_string(), "File: part.stl\nTris: 2\nVertices: 5");
+    }
 }
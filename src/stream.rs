@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use crate::header;
+use crate::manip::VertexSet;
+
+/*
+ *  A binary STL can be worked on in two passes straight off the disk, for scans too big to
+ *  hold every triangle in memory.
+ *
+ *  Pass one reads the triangles a record at a time and keeps only the table of unique
+ *  vertices, in the order first seen. The vertex channel works on that list like it would on
+ *  any other. Pass two reads the records again and writes each one out straight away, with its
+ *  vertices swapped for the written ones and the normal and attribute bytes copied as they
+ *  are. Memory stays proportional to the unique vertices.
+ *
+ *  Only the vertex channel can run this way: rotation and triangle order need every triangle
+ *  at once, and an ASCII STL has no fixed size records to read.
+ */
+const RECORD_BYTES: usize = 50;
+const HEADER_BYTES: u64 = header::STL_HEADER_BYTES as u64 + 4;
+
+pub struct StreamedStl {
+    path: String,
+    pub header: [u8; header::STL_HEADER_BYTES],
+    pub num_triangles: u32,
+    // the unique vertices as f32 bit patterns, first seen first, and where each one is in that list
+    pub vertices: Vec<[u32; 3]>,
+    index: HashMap<[u32; 3], u32>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn record_vertex(record: &[u8; RECORD_BYTES], corner: usize) -> [u32; 3] {
+    // the normal takes the first 12 bytes, then three vertices of three little endian f32
+    let start = 12 + corner * 12;
+    let coordinate = |c: usize| {
+        let at = start + c * 4;
+        u32::from_le_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]])
+    };
+    [coordinate(0), coordinate(1), coordinate(2)]
+}
+
+fn open_records(path: &str) -> io::Result<(BufReader<File>, [u8; header::STL_HEADER_BYTES], u32)> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut banner = [0u8; header::STL_HEADER_BYTES];
+    let mut count = [0u8; 4];
+    reader
+        .read_exact(&mut banner)
+        .and_then(|_| reader.read_exact(&mut count))
+        .map_err(|_| invalid("too short for a binary STL".to_string()))?;
+    let num_triangles = u32::from_le_bytes(count);
+    if HEADER_BYTES + RECORD_BYTES as u64 * num_triangles as u64 != size {
+        return Err(invalid(format!(
+            "{} triangles don't fill {} bytes, only a binary STL can be streamed",
+            num_triangles, size
+        )));
+    }
+    Ok((reader, banner, num_triangles))
+}
+
+pub fn scan_stl(path: &str) -> io::Result<StreamedStl> {
+    // pass one
+    let (mut reader, header, num_triangles) = open_records(path)?;
+    let mut vertices = Vec::<[u32; 3]>::new();
+    let mut index = HashMap::<[u32; 3], u32>::new();
+    let mut record = [0u8; RECORD_BYTES];
+    for _ in 0..num_triangles {
+        reader.read_exact(&mut record)?;
+        for corner in 0..3 {
+            let vertex = record_vertex(&record, corner);
+            index.entry(vertex).or_insert_with(|| {
+                vertices.push(vertex);
+                (vertices.len() - 1) as u32
+            });
+        }
+    }
+    Ok(StreamedStl {
+        path: path.to_string(),
+        header,
+        num_triangles,
+        vertices,
+        index,
+    })
+}
+
+impl StreamedStl {
+    pub fn write<W: Write>(&self, writer: &mut W, written: Option<&VertexSet>) -> io::Result<()> {
+        /*
+         *  Pass two. written replaces vertices, index for index, none writes the file back as
+         *  it was. The file must not have changed since scan_stl.
+         */
+        let written = match written {
+            Some(VertexSet::Single(written)) if written.len() == self.vertices.len() => {
+                Some(written)
+            }
+            Some(other) => {
+                return Err(invalid(format!(
+                    "{} vertices to write back into {} streamed vertices",
+                    other.len(),
+                    self.vertices.len()
+                )));
+            }
+            None => None,
+        };
+        let (mut reader, _, num_triangles) = open_records(&self.path)?;
+        if num_triangles != self.num_triangles {
+            return Err(invalid(format!("{} changed while streaming", self.path)));
+        }
+
+        let mut writer = BufWriter::new(writer);
+        writer.write_all(&self.header)?;
+        writer.write_all(&self.num_triangles.to_le_bytes())?;
+        let mut record = [0u8; RECORD_BYTES];
+        for _ in 0..num_triangles {
+            reader.read_exact(&mut record)?;
+            if let Some(written) = written {
+                for corner in 0..3 {
+                    let vertex = record_vertex(&record, corner);
+                    let index = *self
+                        .index
+                        .get(&vertex)
+                        .ok_or_else(|| invalid(format!("{} changed while streaming", self.path)))?;
+                    for (c, coordinate) in written[index as usize].iter().enumerate() {
+                        let at = 12 + corner * 12 + c * 4;
+                        record[at..at + 4].copy_from_slice(&coordinate.to_le_bytes());
+                    }
+                }
+            }
+            writer.write_all(&record)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::IndexedMesh;
+
+    #[test]
+    fn test_streamed_matches_indexed() {
+        let f = |n: u32| (n as f32) * 1.5 + 0.25;
+        let triangles: Vec<stl::Triangle> = (0..40)
+            .map(|i| stl::Triangle {
+                normal: [0.0, 0.0, 1.0],
+                v1: [f(i), f(i + 1), f(i + 2)],
+                v2: [f(i + 3), f(i), f(i + 7)],
+                v3: [f(i % 5), f(i + 5), f(i)],
+                attr_byte_count: i as u16,
+            })
+            .collect();
+        let stl = stl::BinaryStlFile {
+            header: stl::BinaryStlHeader {
+                header: [b'x'; 80],
+                num_triangles: triangles.len() as u32,
+            },
+            triangles,
+        };
+        let path = std::env::temp_dir().join(format!("stream-test-{}.stl", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        stl::write_stl(&mut File::create(&path).unwrap(), &stl).unwrap();
+
+        let streamed = scan_stl(&path).unwrap();
+        let mesh = IndexedMesh::from_triangles(&stl.triangles);
+        assert_eq!(streamed.vertices, mesh.vertex_bits());
+
+        let mut untouched = Vec::<u8>::new();
+        streamed.write(&mut untouched, None).unwrap();
+        assert_eq!(untouched, std::fs::read(&path).unwrap());
+
+        let mut moved = streamed.vertices.clone();
+        moved[3][1] ^= 1;
+        let mut written = Vec::<u8>::new();
+        streamed
+            .write(&mut written, Some(&VertexSet::Single(moved.clone())))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let reread = stl::read_stl(&mut io::Cursor::new(written)).unwrap();
+        let expected = mesh.write_triangles(
+            &crate::mesh::single_vertices(&moved),
+            &crate::mesh::file_order(stl.triangles.len()),
+            &[],
+        );
+        assert!(reread.triangles == expected);
+        assert_eq!(reread.header.header, stl.header.header);
+    }
+}
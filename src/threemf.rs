@@ -0,0 +1,310 @@
+use std::io;
+use std::io::Write;
+
+use crate::zip::{self, ZipArchive};
+
+/*
+ *  A 3MF project is a zip package. The geometry is XML in 3D/3dmodel.model: every mesh object
+ *  lists its vertices as `<vertex x="..." y="..." z="..."/>` and its triangles as indices into
+ *  that list. Thumbnails, slicer settings and everything else live in other files of the
+ *  package, which are copied over as they are (see zip.rs).
+ *
+ *  Coordinates are decimal text read as f32, like OBJ. Only the attribute values of vertices
+ *  that moved are reprinted, as the shortest decimal that reads back as the same f32, the rest
+ *  of the model file is written back untouched.
+ */
+pub const MODEL_PATH: &str = "3D/3dmodel.model";
+
+pub struct ThreeMfFile {
+    archive: ZipArchive,
+    model: Vec<u8>,
+    // where x, y and z of every vertex are in the model, as start and end offsets
+    spans: Vec<[(usize, usize); 3]>,
+    original_positions: Vec<[f32; 3]>,
+    positions: Vec<[f32; 3]>,
+    corners: Vec<[usize; 3]>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    closing: bool,
+    // name, value and where the value is
+    attributes: Vec<(&'a str, &'a str, (usize, usize))>,
+}
+
+fn local_name(name: &str) -> &str {
+    // `m:vertex` is a vertex too, whatever the namespace prefix
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn parse_tag(text: &str, start: usize, end: usize) -> io::Result<Tag<'_>> {
+    // text[start..end] is what's between `<` and `>`
+    let inner = &text[start..end];
+    let closing = inner.starts_with('/');
+    let body = inner.trim_start_matches('/').trim_end_matches('/');
+    let name_end = body
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(body.len());
+    let name = local_name(&body[..name_end]);
+    let body_start = start + (inner.len() - inner.trim_start_matches('/').len());
+
+    let mut attributes = Vec::<(&str, &str, (usize, usize))>::new();
+    let mut rest = &body[name_end..];
+    let mut offset = body_start + name_end;
+    while let Some(equals) = rest.find('=') {
+        let attribute = rest[..equals].trim();
+        let after = &rest[equals + 1..];
+        let quoted = after.trim_start();
+        let quote = quoted
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| invalid(format!("unquoted XML attribute `{}`", attribute)))?;
+        let value_offset = equals + 1 + (after.len() - quoted.len()) + 1;
+        let value_length = rest[value_offset..]
+            .find(quote)
+            .ok_or_else(|| invalid(format!("unterminated XML attribute `{}`", attribute)))?;
+        let value = &rest[value_offset..value_offset + value_length];
+        let value_start = offset + value_offset;
+        attributes.push((
+            local_name(attribute),
+            value,
+            (value_start, value_start + value_length),
+        ));
+        let consumed = value_offset + value_length + 1;
+        rest = &rest[consumed..];
+        offset += consumed;
+    }
+    Ok(Tag {
+        name,
+        closing,
+        attributes,
+    })
+}
+
+fn attribute<'a>(tag: &Tag<'a>, name: &str) -> io::Result<(&'a str, (usize, usize))> {
+    tag.attributes
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, value, span)| (*value, *span))
+        .ok_or_else(|| invalid(format!("<{}> has no `{}`", tag.name, name)))
+}
+
+pub fn read_3mf(bytes: Vec<u8>) -> io::Result<ThreeMfFile> {
+    let archive = zip::read_zip(bytes)?;
+    let model = archive.read(MODEL_PATH)?;
+    let text = std::str::from_utf8(&model).map_err(|e| invalid(e.to_string()))?;
+
+    let mut spans = Vec::<[(usize, usize); 3]>::new();
+    let mut positions = Vec::<[f32; 3]>::new();
+    let mut corners = Vec::<[usize; 3]>::new();
+    // triangles index into the vertices of their own mesh
+    let mut mesh_start: usize = 0;
+
+    let mut position: usize = 0;
+    while let Some(open) = text[position..].find('<').map(|p| position + p) {
+        let skip_to = |marker: &str| {
+            text[open..]
+                .find(marker)
+                .map(|p| open + p + marker.len())
+                .ok_or_else(|| invalid("unterminated XML markup".to_string()))
+        };
+        if text[open..].starts_with("<!--") {
+            position = skip_to("-->")?;
+            continue;
+        }
+        if text[open..].starts_with("<![CDATA[") {
+            position = skip_to("]]>")?;
+            continue;
+        }
+        let close = skip_to(">")?;
+        position = close;
+        if text[open..].starts_with("<?") || text[open..].starts_with("<!") {
+            continue;
+        }
+        let tag = parse_tag(text, open + 1, close - 1)?;
+        if tag.closing {
+            continue;
+        }
+        match tag.name {
+            "vertices" => mesh_start = positions.len(),
+            "vertex" => {
+                let mut vertex = [0f32; 3];
+                let mut span = [(0usize, 0usize); 3];
+                for (axis, name) in ["x", "y", "z"].iter().enumerate() {
+                    let (value, value_span) = attribute(&tag, name)?;
+                    vertex[axis] = value
+                        .trim()
+                        .parse::<f32>()
+                        .map_err(|_| invalid(format!("`{}` is not a number", value)))?;
+                    span[axis] = value_span;
+                }
+                positions.push(vertex);
+                spans.push(span);
+            }
+            "triangle" => {
+                let mut corner = [0usize; 3];
+                for (i, name) in ["v1", "v2", "v3"].iter().enumerate() {
+                    let (value, _) = attribute(&tag, name)?;
+                    let index = value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| invalid(format!("`{}` is not a vertex index", value)))?;
+                    if mesh_start + index >= positions.len() {
+                        return Err(invalid(format!(
+                            "triangle vertex {} is out of range",
+                            index
+                        )));
+                    }
+                    corner[i] = mesh_start + index;
+                }
+                corners.push(corner);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ThreeMfFile {
+        archive,
+        model,
+        spans,
+        original_positions: positions.clone(),
+        positions,
+        corners,
+    })
+}
+
+impl ThreeMfFile {
+    pub fn triangles(&self) -> Vec<stl::Triangle> {
+        self.corners
+            .iter()
+            .map(|c| stl::Triangle {
+                normal: [0.0, 0.0, 0.0],
+                v1: self.positions[c[0]],
+                v2: self.positions[c[1]],
+                v3: self.positions[c[2]],
+                attr_byte_count: 0,
+            })
+            .collect()
+    }
+
+    pub fn set_triangles(&mut self, triangles: &[stl::Triangle]) -> io::Result<()> {
+        // triangles must be the ones from triangles(), in the same order and the same rotation
+        if triangles.len() != self.corners.len() {
+            return Err(invalid(format!(
+                "{} triangles to write back into {} 3MF triangles",
+                triangles.len(),
+                self.corners.len()
+            )));
+        }
+        for (corner, tri) in self.corners.iter().zip(triangles.iter()) {
+            self.positions[corner[0]] = tri.v1;
+            self.positions[corner[1]] = tri.v2;
+            self.positions[corner[2]] = tri.v3;
+        }
+        Ok(())
+    }
+
+    fn written_model(&self) -> io::Result<Vec<u8>> {
+        let mut changes = Vec::<((usize, usize), f32)>::new();
+        for (i, position) in self.positions.iter().enumerate() {
+            for (axis, value) in position.iter().enumerate() {
+                if value.to_bits() != self.original_positions[i][axis].to_bits() {
+                    if !value.is_finite() {
+                        return Err(invalid(format!(
+                            "{} can't be written exactly in a 3MF",
+                            value
+                        )));
+                    }
+                    changes.push((self.spans[i][axis], *value));
+                }
+            }
+        }
+        changes.sort_by_key(|((start, _), _)| *start);
+
+        let mut model = Vec::<u8>::with_capacity(self.model.len());
+        let mut written: usize = 0;
+        for ((start, end), value) in changes {
+            model.extend_from_slice(&self.model[written..start]);
+            model.extend_from_slice(format!("{}", value).as_bytes());
+            written = end;
+        }
+        model.extend_from_slice(&self.model[written..]);
+        Ok(model)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.archive
+            .write_replacing(writer, MODEL_PATH, &self.written_model()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02">
+ <!-- <vertex x="9" y="9" z="9"/> is a comment -->
+ <resources>
+  <object id="1" type="model">
+   <mesh>
+    <vertices>
+     <vertex x="0" y="0" z="0"/>
+     <vertex x="10.5" y='0' z="0" />
+     <vertex x="10.5" y="10.5" z="0"/>
+    </vertices>
+    <triangles><triangle v1="0" v2="1" v3="2"/></triangles>
+   </mesh>
+  </object>
+  <object id="2" type="model">
+   <mesh>
+    <vertices>
+     <vertex x="1" y="1" z="1"/>
+     <vertex x="2" y="1" z="1"/>
+     <vertex x="2" y="2" z="1"/>
+    </vertices>
+    <triangles><triangle v1="2" v2="1" v3="0"/></triangles>
+   </mesh>
+  </object>
+ </resources>
+ <build><item objectid="1"/><item objectid="2"/></build>
+</model>
+"#;
+
+    #[test]
+    fn test_3mf_roundtrip() {
+        let thumbnail: Vec<u8> = (0..200).map(|i| (i * 13 % 256) as u8).collect();
+        let bytes = zip::build_zip(&[
+            ("[Content_Types].xml", b"<Types/>", true),
+            (MODEL_PATH, MODEL.as_bytes(), true),
+            ("Metadata/thumbnail.png", &thumbnail, false),
+        ]);
+        let mut package = read_3mf(bytes).unwrap();
+        let mut triangles = package.triangles();
+        assert_eq!(triangles.len(), 2);
+        // the second mesh counts its vertices from its own first one
+        assert_eq!(triangles[1].v1, [2.0, 2.0, 1.0]);
+        assert_eq!(package.written_model().unwrap(), MODEL.as_bytes());
+
+        let nudged = f32::from_bits(10.5f32.to_bits() + 1);
+        triangles[0].v2 = [nudged, 0.0, 0.0];
+        package.set_triangles(&triangles).unwrap();
+        let mut written = Vec::<u8>::new();
+        package.write(&mut written).unwrap();
+
+        let reread = read_3mf(written).unwrap();
+        let model = String::from_utf8(reread.model.clone()).unwrap();
+        assert!(model.contains(r#"<vertex x="10.500001" y='0' z="0" />"#));
+        assert_eq!(model.len(), MODEL.len() + "00001".len());
+        assert_eq!(reread.triangles()[0].v2[0], nudged);
+        assert_eq!(
+            reread.archive.read("Metadata/thumbnail.png").unwrap(),
+            thumbnail
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::io;
+use std::io::{Read, Write};
+
+/*
+ *  Just enough zip to swap one file inside a package and leave everything else alone.
+ *
+ *  Every other entry is copied as raw bytes, compressed data, data descriptor and all, so a
+ *  thumbnail or slicer config comes back byte for byte no matter what compressed it. Only the
+ *  replaced entry is compressed again, and the central directory is copied with its sizes,
+ *  CRC and the shifted offsets patched in. Zip64 and multi-disk archives aren't supported.
+ */
+const LOCAL_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_SIGNATURE: u32 = 0x0201_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const LOCAL_HEADER_BYTES: usize = 30;
+const CENTRAL_HEADER_BYTES: usize = 46;
+const END_RECORD_BYTES: usize = 22;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+struct ZipEntry {
+    name: String,
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    local_offset: usize,
+    // the whole central directory record, name, extra field and comment included
+    central_record: (usize, usize),
+}
+
+pub struct ZipArchive {
+    bytes: Vec<u8>,
+    entries: Vec<ZipEntry>,
+    central_offset: usize,
+    end_offset: usize,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn u16_at(bytes: &[u8], position: usize) -> io::Result<u16> {
+    bytes
+        .get(position..position + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid("zip archive ends early".to_string()))
+}
+
+fn u32_at(bytes: &[u8], position: usize) -> io::Result<u32> {
+    bytes
+        .get(position..position + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid("zip archive ends early".to_string()))
+}
+
+fn put_u16(bytes: &mut [u8], position: usize, value: u16) {
+    bytes[position..position + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(bytes: &mut [u8], position: usize, value: u32) {
+    bytes[position..position + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn zip32(value: usize) -> io::Result<u32> {
+    // 0xffffffff means the real value is in a zip64 record
+    match u32::try_from(value) {
+        Ok(value) if value != u32::MAX => Ok(value),
+        _ => Err(invalid("zip64 archives aren't supported".to_string())),
+    }
+}
+
+pub fn read_zip(bytes: Vec<u8>) -> io::Result<ZipArchive> {
+    // the end record is last, followed only by a comment of up to 64k
+    let search_start = bytes
+        .len()
+        .saturating_sub(END_RECORD_BYTES + u16::MAX as usize);
+    let end_offset = (search_start..=bytes.len().saturating_sub(END_RECORD_BYTES))
+        .rev()
+        .find(|p| u32_at(&bytes, *p).ok() == Some(END_SIGNATURE))
+        .ok_or_else(|| invalid("not a zip archive".to_string()))?;
+    let entry_count = u16_at(&bytes, end_offset + 10)? as usize;
+    let central_size = u32_at(&bytes, end_offset + 12)?;
+    let central_offset = u32_at(&bytes, end_offset + 16)?;
+    if u16_at(&bytes, end_offset + 4)? != 0
+        || central_size == u32::MAX
+        || central_offset == u32::MAX
+    {
+        return Err(invalid(
+            "zip64 and multi-disk archives aren't supported".to_string(),
+        ));
+    }
+
+    let mut entries = Vec::<ZipEntry>::new();
+    let mut position = central_offset as usize;
+    for _ in 0..entry_count {
+        if u32_at(&bytes, position)? != CENTRAL_SIGNATURE {
+            return Err(invalid("broken zip central directory".to_string()));
+        }
+        let name_bytes = u16_at(&bytes, position + 28)? as usize;
+        let extra_bytes = u16_at(&bytes, position + 30)? as usize;
+        let comment_bytes = u16_at(&bytes, position + 32)? as usize;
+        let name_start = position + CENTRAL_HEADER_BYTES;
+        let name = bytes
+            .get(name_start..name_start + name_bytes)
+            .map(|n| String::from_utf8_lossy(n).into_owned())
+            .ok_or_else(|| invalid("zip archive ends early".to_string()))?;
+        let record_end = name_start + name_bytes + extra_bytes + comment_bytes;
+        let entry = ZipEntry {
+            name,
+            flags: u16_at(&bytes, position + 8)?,
+            method: u16_at(&bytes, position + 10)?,
+            crc: u32_at(&bytes, position + 16)?,
+            compressed_size: u32_at(&bytes, position + 20)?,
+            size: u32_at(&bytes, position + 24)?,
+            local_offset: u32_at(&bytes, position + 42)? as usize,
+            central_record: (position, record_end),
+        };
+        if entry.compressed_size == u32::MAX || entry.size == u32::MAX {
+            return Err(invalid("zip64 archives aren't supported".to_string()));
+        }
+        if record_end > end_offset {
+            return Err(invalid("broken zip central directory".to_string()));
+        }
+        entries.push(entry);
+        position = record_end;
+    }
+
+    // every entry starts at its own offset, ahead of the central directory
+    let mut offsets: Vec<usize> = entries.iter().map(|e| e.local_offset).collect();
+    offsets.sort_unstable();
+    if offsets.windows(2).any(|pair| pair[0] == pair[1])
+        || offsets.last() >= Some(&(central_offset as usize))
+    {
+        return Err(invalid("broken zip entry offsets".to_string()));
+    }
+
+    Ok(ZipArchive {
+        bytes,
+        entries,
+        central_offset: central_offset as usize,
+        end_offset,
+    })
+}
+
+impl ZipArchive {
+    fn entry(&self, name: &str) -> io::Result<&ZipEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| invalid(format!("`{}` isn't in the package", name)))
+    }
+
+    fn slice(&self, start: usize, end: usize) -> io::Result<&[u8]> {
+        self.bytes
+            .get(start..end)
+            .ok_or_else(|| invalid("broken zip entry offsets".to_string()))
+    }
+
+    fn data_range(&self, entry: &ZipEntry) -> io::Result<(usize, usize)> {
+        // the local header can have its own extra field, different from the central one
+        let local = entry.local_offset;
+        if u32_at(&self.bytes, local)? != LOCAL_SIGNATURE {
+            return Err(invalid(format!("broken zip entry `{}`", entry.name)));
+        }
+        let name_bytes = u16_at(&self.bytes, local + 26)? as usize;
+        let extra_bytes = u16_at(&self.bytes, local + 28)? as usize;
+        let start = local + LOCAL_HEADER_BYTES + name_bytes + extra_bytes;
+        let end = start + entry.compressed_size as usize;
+        if end > self.bytes.len() {
+            return Err(invalid("zip archive ends early".to_string()));
+        }
+        Ok((start, end))
+    }
+
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entry(name)?;
+        let (start, end) = self.data_range(entry)?;
+        let data = &self.bytes[start..end];
+        let contents = match entry.method {
+            METHOD_STORED => data.to_vec(),
+            METHOD_DEFLATED => {
+                let mut contents = Vec::<u8>::with_capacity(entry.size as usize);
+                DeflateDecoder::new(data).read_to_end(&mut contents)?;
+                contents
+            }
+            method => {
+                return Err(invalid(format!(
+                    "`{}` uses unsupported zip compression {}",
+                    name, method
+                )));
+            }
+        };
+        if crc32fast::hash(&contents) != entry.crc {
+            return Err(invalid(format!("`{}` fails its CRC check", name)));
+        }
+        Ok(contents)
+    }
+
+    pub fn write_replacing<W: Write>(
+        &self,
+        writer: &mut W,
+        name: &str,
+        contents: &[u8],
+    ) -> io::Result<()> {
+        let replaced = self.entry(name)?;
+        let compressed = match replaced.method {
+            METHOD_STORED => contents.to_vec(),
+            _ => {
+                let mut encoder = DeflateEncoder::new(Vec::<u8>::new(), Compression::default());
+                encoder.write_all(contents)?;
+                encoder.finish()?
+            }
+        };
+        let method = match replaced.method {
+            METHOD_STORED => METHOD_STORED,
+            _ => METHOD_DEFLATED,
+        };
+        let crc = crc32fast::hash(contents);
+        // the sizes go in the header, so the replaced entry doesn't need a data descriptor
+        let flags = replaced.flags & !FLAG_DATA_DESCRIPTOR;
+
+        let mut in_order: Vec<&ZipEntry> = self.entries.iter().collect();
+        in_order.sort_by_key(|e| e.local_offset);
+        let first = in_order.first().map(|e| e.local_offset).unwrap_or(0);
+        writer.write_all(self.slice(0, first)?)?;
+
+        let mut new_offsets = Vec::<usize>::new();
+        let mut written = first;
+        for (i, entry) in in_order.iter().enumerate() {
+            // each entry runs up to the next one, or up to the central directory
+            let end = in_order
+                .get(i + 1)
+                .map(|e| e.local_offset)
+                .unwrap_or(self.central_offset);
+            new_offsets.push(written);
+            if entry.name != name {
+                writer.write_all(self.slice(entry.local_offset, end)?)?;
+                written += end - entry.local_offset;
+                continue;
+            }
+            let (data_start, _) = self.data_range(entry)?;
+            let mut header = self.slice(entry.local_offset, data_start)?.to_vec();
+            put_u16(&mut header, 6, flags);
+            put_u16(&mut header, 8, method);
+            put_u32(&mut header, 14, crc);
+            put_u32(&mut header, 18, zip32(compressed.len())?);
+            put_u32(&mut header, 22, zip32(contents.len())?);
+            writer.write_all(&header)?;
+            writer.write_all(&compressed)?;
+            written += header.len() + compressed.len();
+        }
+
+        let central_offset = written;
+        for entry in self.entries.iter() {
+            let (start, end) = entry.central_record;
+            let mut record = self.slice(start, end)?.to_vec();
+            let order = in_order
+                .iter()
+                .position(|e| e.local_offset == entry.local_offset)
+                .unwrap();
+            put_u32(&mut record, 42, zip32(new_offsets[order])?);
+            if entry.name == name {
+                put_u16(&mut record, 8, flags);
+                put_u16(&mut record, 10, method);
+                put_u32(&mut record, 16, crc);
+                put_u32(&mut record, 20, zip32(compressed.len())?);
+                put_u32(&mut record, 24, zip32(contents.len())?);
+            }
+            writer.write_all(&record)?;
+            written += record.len();
+        }
+
+        let mut end_record = self.slice(self.end_offset, self.bytes.len())?.to_vec();
+        put_u32(&mut end_record, 12, zip32(written - central_offset)?);
+        put_u32(&mut end_record, 16, zip32(central_offset)?);
+        writer.write_all(&end_record)
+    }
+}
+
+#[cfg(test)]
+pub fn build_zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+    // a plain archive to test against, (name, contents, deflated)
+    let mut bytes = Vec::<u8>::new();
+    let mut central = Vec::<u8>::new();
+    for (name, contents, deflated) in files.iter() {
+        let (method, data) = match deflated {
+            true => {
+                let mut encoder = DeflateEncoder::new(Vec::<u8>::new(), Compression::best());
+                encoder.write_all(contents).unwrap();
+                (METHOD_DEFLATED, encoder.finish().unwrap())
+            }
+            false => (METHOD_STORED, contents.to_vec()),
+        };
+        let mut fields = Vec::<u8>::new();
+        fields.extend(20u16.to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+        fields.extend(method.to_le_bytes());
+        fields.extend([0u8; 4]);
+        fields.extend(crc32fast::hash(contents).to_le_bytes());
+        fields.extend((data.len() as u32).to_le_bytes());
+        fields.extend((contents.len() as u32).to_le_bytes());
+        fields.extend((name.len() as u16).to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+
+        central.extend(CENTRAL_SIGNATURE.to_le_bytes());
+        central.extend(20u16.to_le_bytes());
+        central.extend(&fields);
+        central.extend([0u8; 6]);
+        central.extend(0u32.to_le_bytes());
+        central.extend((bytes.len() as u32).to_le_bytes());
+        central.extend(name.as_bytes());
+
+        bytes.extend(LOCAL_SIGNATURE.to_le_bytes());
+        bytes.extend(&fields);
+        bytes.extend(name.as_bytes());
+        bytes.extend(&data);
+    }
+    let central_offset = bytes.len() as u32;
+    bytes.extend(&central);
+    bytes.extend(END_SIGNATURE.to_le_bytes());
+    bytes.extend([0u8; 4]);
+    bytes.extend((files.len() as u16).to_le_bytes());
+    bytes.extend((files.len() as u16).to_le_bytes());
+    bytes.extend((central.len() as u32).to_le_bytes());
+    bytes.extend(central_offset.to_le_bytes());
+    bytes.extend(0u16.to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_entry() {
+        let thumbnail: Vec<u8> = (0..300).map(|i| (i * 7 % 256) as u8).collect();
+        let config = b"; layer_height = 0.2\n".repeat(20);
+        let bytes = build_zip(&[
+            ("Metadata/thumbnail.png", &thumbnail, false),
+            ("3D/3dmodel.model", b"<model>old</model>", true),
+            ("Metadata/config.ini", &config, true),
+        ]);
+        let archive = read_zip(bytes.clone()).unwrap();
+        assert_eq!(archive.read("Metadata/config.ini").unwrap(), config);
+        assert!(archive.read("missing").is_err());
+
+        let mut written = Vec::<u8>::new();
+        archive
+            .write_replacing(
+                &mut written,
+                "3D/3dmodel.model",
+                b"<model>a new model</model>",
+            )
+            .unwrap();
+        let rewritten = read_zip(written.clone()).unwrap();
+        assert_eq!(
+            rewritten.read("3D/3dmodel.model").unwrap(),
+            b"<model>a new model</model>"
+        );
+        assert_eq!(rewritten.read("Metadata/thumbnail.png").unwrap(), thumbnail);
+        assert_eq!(rewritten.read("Metadata/config.ini").unwrap(), config);
+
+        // entries that weren't replaced are the same bytes, only shifted
+        let config_entry = rewritten.entry("Metadata/config.ini").unwrap();
+        let (start, end) = rewritten.data_range(config_entry).unwrap();
+        let (old_start, old_end) = archive
+            .data_range(archive.entry("Metadata/config.ini").unwrap())
+            .unwrap();
+        assert_eq!(written[start..end], bytes[old_start..old_end]);
+    }
+
+    #[test]
+    fn test_broken_offsets() {
+        let bytes = build_zip(&[
+            ("3D/3dmodel.model", b"<model>old</model>", true),
+            ("Metadata/config.ini", b"; layer_height = 0.2", false),
+        ]);
+        let archive = read_zip(bytes.clone()).unwrap();
+        let second = archive.entries[1].central_record.0 + 42;
+        let central_offset = archive.central_offset as u32;
+
+        // an entry past the central directory, or on top of another one, is refused
+        for local_offset in [central_offset, central_offset + 10, 0] {
+            let mut broken = bytes.clone();
+            put_u32(&mut broken, second, local_offset);
+            let error = read_zip(broken).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}